@@ -0,0 +1,115 @@
+use crate::audio::{AudioListener, AudioSamples};
+use eyre::{Result, bail, eyre};
+use rodio::cpal::{
+    self, FromSample, SampleFormat, SizedSample,
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+use std::sync::{Arc, Mutex};
+
+/// Names of all audio input devices of the default host.
+///
+/// On Linux, monitors of PulseAudio/PipeWire outputs are reached through the `pulse` or `pipewire`
+/// device. The monitored source is picked with `pavucontrol` or the `PULSE_SOURCE` environment
+/// variable.
+pub fn input_devices() -> Result<Vec<String>> {
+    let host = cpal::default_host();
+    let devices = host.input_devices().map_err(|e| eyre!("Failed to list input devices: {e}"))?;
+    Ok(devices.filter_map(|device| device.name().ok()).collect())
+}
+
+/// Name of the default audio input device
+pub fn default_input_device() -> Option<String> {
+    cpal::default_host().default_input_device().and_then(|device| device.name().ok())
+}
+
+/// Records audio from an input device and forwards the samples to a listener
+pub struct Capture {
+    _stream: cpal::Stream,
+    device: String,
+}
+
+impl Capture {
+    /// Starts capturing from the device with the given name or the default input device.
+    pub fn start(
+        device: Option<&str>,
+        listener: Arc<Mutex<dyn AudioListener + Send>>,
+    ) -> Result<Self> {
+        let host = cpal::default_host();
+        let device = match device {
+            Some(name) => host
+                .input_devices()
+                .map_err(|e| eyre!("Failed to list input devices: {e}"))?
+                .find(|device| device.name().is_ok_and(|n| n == name))
+                .ok_or_else(|| eyre!("Unknown input device '{name}'. Use `deiss list-devices`."))?,
+            None => host.default_input_device().ok_or_else(|| eyre!("No default input device"))?,
+        };
+        let name = device.name().unwrap_or_default();
+
+        let supported = device
+            .default_input_config()
+            .map_err(|e| eyre!("Failed to query input config of '{name}': {e}"))?;
+        let config = supported.config();
+
+        log::info!(
+            "Capturing from '{name}' ({} Hz, {} channels, {:?})",
+            config.sample_rate.0,
+            config.channels,
+            supported.sample_format()
+        );
+
+        let stream = match supported.sample_format() {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, listener),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, listener),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, listener),
+            SampleFormat::I32 => build_stream::<i32>(&device, &config, listener),
+            format => bail!("Unsupported sample format {format:?} of '{name}'"),
+        }?;
+
+        stream.play().map_err(|e| eyre!("Failed to start capture from '{name}': {e}"))?;
+
+        Ok(Self { _stream: stream, device: name })
+    }
+
+    /// Name of the device which is recorded
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+}
+
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    listener: Arc<Mutex<dyn AudioListener + Send>>,
+) -> Result<cpal::Stream>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+{
+    let sample_rate = config.sample_rate.0;
+    let channels = config.channels;
+    let buffer_size = listener.lock().unwrap().buffer_size(sample_rate) * channels as usize;
+    let mut buffer = Vec::with_capacity(buffer_size);
+
+    let on_data = move |data: &[T], _: &cpal::InputCallbackInfo| {
+        for &sample in data {
+            buffer.push(f32::from_sample_(sample));
+
+            // Forward buffered samples to listener when buffer is full
+            if buffer.len() >= buffer_size {
+                if let Ok(mut listener) = listener.lock() {
+                    let samples =
+                        AudioSamples::new(core::mem::take(&mut buffer), sample_rate, channels);
+                    listener.on_samples(&samples);
+                } else {
+                    buffer.clear();
+                }
+            }
+        }
+    };
+
+    let on_error = |err| log::error!("Audio capture error: {err}");
+
+    device
+        .build_input_stream(config, on_data, on_error, None)
+        .map_err(|e| eyre!("Failed to open input stream: {e}"))
+}
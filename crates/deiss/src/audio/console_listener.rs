@@ -20,7 +20,7 @@ impl Default for ConsoleAudioListener {
 }
 
 impl AudioListener for ConsoleAudioListener {
-    fn buffer_size(&self) -> usize {
+    fn buffer_size(&self, _sample_rate: u32) -> usize {
         4096
     }
 
@@ -2,9 +2,14 @@ use crate::audio::AudioSamples;
 
 /// Trait for listening to audio samples during playback
 pub trait AudioListener {
-    /// [on_samples] is called when this number of samples is accumulated
-    fn buffer_size(&self) -> usize;
+    /// [Self::on_samples] is called when this number of frames (samples per channel) is
+    /// accumulated. The size depends on the sample rate so that a buffer covers the same time span.
+    fn buffer_size(&self, sample_rate: u32) -> usize;
 
     /// Called when audio samples are read from the audio file
     fn on_samples(&mut self, samples: &AudioSamples);
+
+    /// Called when playback jumps to another position and past samples are no longer related to
+    /// the upcoming ones
+    fn on_seek(&mut self) {}
 }
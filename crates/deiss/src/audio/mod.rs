@@ -1,32 +1,68 @@
 //! Audio playback module with listener support
 
+mod capture;
 mod console_listener;
 mod listener;
 mod playback;
+mod playlist;
 
 use std::ops::Deref;
 
+pub use capture::{Capture, default_input_device, input_devices};
 pub use console_listener::ConsoleAudioListener;
 pub use listener::AudioListener;
-pub use playback::Playback;
+pub use playback::{DecodedSource, Playback, decode_file};
+pub use playlist::*;
+
+/// Arrangement of the channels in interleaved audio frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Mono,
+
+    /// Left, right
+    Stereo,
+
+    /// More than two channels in WAVE order: front left, front right, center, LFE, followed by
+    /// pairs of left/right surround channels
+    Surround(u16),
+}
+
+impl ChannelLayout {
+    pub fn from_count(channels: u16) -> Self {
+        match channels {
+            0 | 1 => ChannelLayout::Mono,
+            2 => ChannelLayout::Stereo,
+            n => ChannelLayout::Surround(n),
+        }
+    }
+
+    /// Number of channels in a frame
+    pub fn count(&self) -> u16 {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+            ChannelLayout::Surround(n) => *n,
+        }
+    }
+}
 
 pub struct AudioSamples {
-    /// Slice of f32 audio samples (interleaved if stereo)
-    samples: Vec<u16>,
+    /// Interleaved samples normalized to -1.0 to 1.0
+    samples: Vec<f32>,
 
     /// The sample rate of the audio
     sample_rate: u32,
 
-    /// Number of audio channels (1 for mono, 2 for stereo)
-    channels: u16,
+    /// Arrangement of the interleaved channels
+    layout: ChannelLayout,
 }
 
 impl AudioSamples {
-    pub fn new(samples: Vec<u16>, sample_rate: u32, channels: u16) -> Self {
-        AudioSamples { samples, sample_rate, channels }
+    pub fn new(samples: Vec<f32>, sample_rate: u32, channels: u16) -> Self {
+        AudioSamples { samples, sample_rate, layout: ChannelLayout::from_count(channels) }
     }
 
-    pub fn samples(&self) -> &[u16] {
+    pub fn samples(&self) -> &[f32] {
         &self.samples
     }
 
@@ -34,15 +70,176 @@ impl AudioSamples {
         self.sample_rate
     }
 
+    pub fn layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    /// Number of audio channels (1 for mono, 2 for stereo)
     pub fn channels(&self) -> u16 {
-        self.channels
+        self.layout.count()
+    }
+
+    /// Number of frames, i.e. samples per channel
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels() as usize
+    }
+
+    /// Converts to another sample rate. Downsampling averages all input frames which fall onto an
+    /// output frame to suppress aliasing, upsampling interpolates linearly.
+    pub fn resample(&self, sample_rate: u32) -> AudioSamples {
+        if sample_rate == self.sample_rate || self.samples.is_empty() {
+            return AudioSamples {
+                samples: self.samples.clone(),
+                sample_rate,
+                layout: self.layout,
+            };
+        }
+
+        let channels = self.channels() as usize;
+        let frames = self.frames();
+        let step = self.sample_rate as f64 / sample_rate as f64;
+        let out_frames = ((frames - 1) as f64 / step) as usize + 1;
+
+        let mut samples = Vec::with_capacity(out_frames * channels);
+        for i in 0..out_frames {
+            let t = i as f64 * step;
+            let i0 = t as usize;
+            if step > 1. {
+                let i1 = ((t + step) as usize).clamp(i0 + 1, frames);
+                for c in 0..channels {
+                    let sum = (i0..i1).map(|j| self.samples[j * channels + c]).sum::<f32>();
+                    samples.push(sum / (i1 - i0) as f32);
+                }
+            } else {
+                let i1 = (i0 + 1).min(frames - 1);
+                let w = (t - i0 as f64) as f32;
+                for c in 0..channels {
+                    let (a, b) = (self.samples[i0 * channels + c], self.samples[i1 * channels + c]);
+                    samples.push(a + w * (b - a));
+                }
+            }
+        }
+
+        AudioSamples { samples, sample_rate, layout: self.layout }
+    }
+
+    /// Interleaved left/right samples. Mono is copied to both channels, surround is down-mixed
+    /// with the center and surround channels attenuated by 3 dB and the LFE channel dropped.
+    pub fn to_stereo(&self) -> Vec<f32> {
+        match self.layout {
+            ChannelLayout::Mono => self.samples.iter().flat_map(|&v| [v, v]).collect(),
+            ChannelLayout::Stereo => self.samples.clone(),
+            ChannelLayout::Surround(n) => {
+                const ATT: f32 = core::f32::consts::FRAC_1_SQRT_2;
+                let n = n as usize;
+                // channels after front left/right, center and LFE come in left/right pairs
+                let surround_pairs = n.saturating_sub(4) / 2;
+                let norm = 1. / (1. + ATT + ATT * surround_pairs as f32);
+                self.samples
+                    .chunks_exact(n)
+                    .flat_map(|frame| {
+                        let center = ATT * frame[2];
+                        let (mut l, mut r) = (frame[0] + center, frame[1] + center);
+                        for pair in frame[4.min(n)..].chunks_exact(2) {
+                            l += ATT * pair[0];
+                            r += ATT * pair[1];
+                        }
+                        [l * norm, r * norm]
+                    })
+                    .collect()
+            }
+        }
     }
 }
 
 impl Deref for AudioSamples {
-    type Target = [u16];
+    type Target = [f32];
 
     fn deref(&self) -> &Self::Target {
         &self.samples
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &[f32], b: &[f32]) {
+        assert_eq!(a.len(), b.len(), "{a:?} != {b:?}");
+        assert!(a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-6), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn mono_and_stereo_to_stereo() {
+        let mono = AudioSamples::new(vec![0.1, -0.2], 44_100, 1);
+        assert_eq!(mono.to_stereo(), [0.1, 0.1, -0.2, -0.2]);
+
+        let stereo = AudioSamples::new(vec![0.1, -0.2, 0.3, 0.4], 44_100, 2);
+        assert_eq!(stereo.to_stereo(), stereo.samples());
+    }
+
+    #[test]
+    fn surround_downmix() {
+        const ATT: f32 = core::f32::consts::FRAC_1_SQRT_2;
+        let norm = 1. / (1. + 2. * ATT);
+
+        // 5.1: FL, FR, C, LFE, SL, SR
+        let frame = |fl, fr, c, lfe, sl, sr| vec![fl, fr, c, lfe, sl, sr];
+        let lfe_only = AudioSamples::new(frame(0., 0., 0., 1., 0., 0.), 48_000, 6);
+        assert_close(&lfe_only.to_stereo(), &[0., 0.]);
+
+        let center = AudioSamples::new(frame(0., 0., 1., 0., 0., 0.), 48_000, 6);
+        assert_close(&center.to_stereo(), &[ATT * norm, ATT * norm]);
+
+        let left = AudioSamples::new(frame(1., 0., 0., 0., 1., 0.), 48_000, 6);
+        assert_close(&left.to_stereo(), &[(1. + ATT) * norm, 0.]);
+
+        // full scale on every channel doesn't clip
+        let full = AudioSamples::new(vec![1.; 8 * 10], 48_000, 8);
+        let stereo = full.to_stereo();
+        assert_eq!(stereo.len(), 20);
+        assert!(stereo.iter().all(|&v| v <= 1. + 1e-6));
+    }
+
+    #[test]
+    fn incomplete_surround_frames_are_dropped() {
+        let samples = AudioSamples::new(vec![0.5; 7], 48_000, 3);
+        assert_eq!(samples.frames(), 2);
+        assert_eq!(samples.to_stereo().len(), 4);
+    }
+
+    #[test]
+    fn resample_same_rate_is_unchanged() {
+        let samples = AudioSamples::new(vec![0.1, 0.2, 0.3, 0.4], 44_100, 2);
+        let same = samples.resample(44_100);
+        assert_eq!(same.samples(), samples.samples());
+        assert_eq!(same.sample_rate(), 44_100);
+    }
+
+    #[test]
+    fn upsample_interpolates_linearly() {
+        let samples = AudioSamples::new(vec![0., 1., 1., -1.], 22_050, 2);
+        let up = samples.resample(44_100);
+        assert_eq!(up.sample_rate(), 44_100);
+        assert_eq!(up.channels(), 2);
+        assert_close(&up, &[0., 1., 0.5, 0., 1., -1.]);
+    }
+
+    #[test]
+    fn downsample_averages_frames() {
+        let samples = AudioSamples::new(vec![0., 1., 0., 3., 1., 1.], 48_000, 1);
+        let down = samples.resample(24_000);
+        assert_close(&down, &[0.5, 1.5, 1.]);
+    }
+
+    #[test]
+    fn resample_keeps_duration_and_level() {
+        let samples = AudioSamples::new(vec![0.25; 48_000 * 2], 48_000, 2);
+        let resampled = samples.resample(44_100);
+        assert!((resampled.frames() as i64 - 44_100).abs() <= 1, "{}", resampled.frames());
+        assert!(resampled.iter().all(|&v| (v - 0.25).abs() < 1e-6));
+
+        let empty = AudioSamples::new(Vec::new(), 48_000, 2).resample(44_100);
+        assert_eq!(empty.frames(), 0);
+    }
+}
@@ -2,11 +2,15 @@ use crate::audio::AudioSamples;
 
 use super::listener::AudioListener;
 use eyre::{Result, eyre};
-use rodio::{Decoder, OutputStream, Sink, Source};
+use rodio::{
+    Decoder, OutputStream, Sink, Source,
+    source::{SamplesConverter, SeekError},
+};
 use std::{
+    collections::VecDeque,
     fs::File,
     io::BufReader,
-    path::Path,
+    path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -14,23 +18,24 @@ use std::{
 /// Custom source wrapper that forwards samples to a listener
 struct MonitoredSource<S>
 where
-    S: Source<Item = u16>,
+    S: Source<Item = f32>,
 {
     source: S,
     listener: Arc<Mutex<dyn AudioListener + Send>>,
     sample_rate: u32,
     channels: u16,
-    buffer: Vec<u16>,
+    buffer: Vec<f32>,
     buffer_size: usize,
 }
 
 impl<S> MonitoredSource<S>
 where
-    S: Source<Item = u16>,
+    S: Source<Item = f32>,
 {
-    fn new(source: S, listener: Arc<Mutex<dyn AudioListener + Send>>, buffer_size: usize) -> Self {
+    fn new(source: S, listener: Arc<Mutex<dyn AudioListener + Send>>) -> Self {
         let sample_rate = source.sample_rate();
         let channels = source.channels();
+        let buffer_size = listener.lock().unwrap().buffer_size(sample_rate) * channels as usize;
         Self {
             source,
             listener,
@@ -44,9 +49,9 @@ where
 
 impl<S> Iterator for MonitoredSource<S>
 where
-    S: Source<Item = u16>,
+    S: Source<Item = f32>,
 {
-    type Item = u16;
+    type Item = f32;
 
     fn next(&mut self) -> Option<Self::Item> {
         let sample = self.source.next()?;
@@ -70,7 +75,7 @@ where
 
 impl<S> Source for MonitoredSource<S>
 where
-    S: Source<Item = u16>,
+    S: Source<Item = f32>,
 {
     fn current_frame_len(&self) -> Option<usize> {
         self.source.current_frame_len()
@@ -87,6 +92,23 @@ where
     fn total_duration(&self) -> Option<Duration> {
         self.source.total_duration()
     }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.source.try_seek(pos)
+    }
+}
+
+/// Decoded audio file converted to f32 samples
+pub type DecodedSource = SamplesConverter<Decoder<BufReader<File>>, f32>;
+
+/// Opens and decodes an audio file without requiring an output device
+pub fn decode_file<P: AsRef<Path>>(path: P) -> Result<DecodedSource> {
+    let file = File::open(path.as_ref()).map_err(|e| eyre!("Failed to open audio file: {}", e))?;
+    let source = Decoder::new(BufReader::new(file))
+        .map_err(|e| eyre!("Failed to decode audio file: {}", e))?;
+
+    // Convert to f32 samples
+    Ok(source.convert_samples())
 }
 
 /// A struct that handles audio playback with listener support
@@ -94,6 +116,14 @@ pub struct Playback {
     _stream: OutputStream,
     sink: Sink,
     listener: Option<Arc<Mutex<dyn AudioListener + Send>>>,
+
+    /// Files of all sources currently queued in the sink. The front is playing.
+    queue: VecDeque<QueuedFile>,
+}
+
+struct QueuedFile {
+    path: PathBuf,
+    duration: Option<Duration>,
 }
 
 impl Playback {
@@ -105,7 +135,7 @@ impl Playback {
         let sink =
             Sink::try_new(&stream_handle).map_err(|e| eyre!("Failed to create sink: {}", e))?;
 
-        Ok(Self { _stream: stream, sink, listener: None })
+        Ok(Self { _stream: stream, sink, listener: None, queue: VecDeque::new() })
     }
 
     /// Sets an audio listener to receive samples during playback
@@ -113,25 +143,70 @@ impl Playback {
         self.listener = Some(listener);
     }
 
-    /// Loads and plays an audio file
-    pub fn play<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let file =
-            File::open(path.as_ref()).map_err(|e| eyre!("Failed to open audio file: {}", e))?;
-        let source = Decoder::new(BufReader::new(file))
-            .map_err(|e| eyre!("Failed to decode audio file: {}", e))?;
+    /// Stops whatever is playing and plays an audio file
+    pub fn play<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.stop();
+        self.append(path)
+    }
 
-        // Convert to f32 samples
-        let source = source.convert_samples();
+    /// Loads an audio file and queues it for gapless playback after all queued files
+    pub fn append<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let source = decode_file(&path)?;
+        let duration = source.total_duration();
 
         // If we have a listener, wrap the source to forward samples
         if let Some(listener) = &self.listener {
-            let buffer_size = listener.lock().unwrap().buffer_size();
-            let monitored_source = MonitoredSource::new(source, Arc::clone(listener), buffer_size);
+            let monitored_source = MonitoredSource::new(source, Arc::clone(listener));
             self.sink.append(monitored_source);
         } else {
             self.sink.append(source);
         }
 
+        self.queue.push_back(QueuedFile { path: path.as_ref().to_path_buf(), duration });
+
+        Ok(())
+    }
+
+    /// Removes files which finished playing from the queue and returns how many did
+    pub fn sync_queue(&mut self) -> usize {
+        let finished = self.queue.len().saturating_sub(self.sink.len());
+        self.queue.drain(..finished);
+        finished
+    }
+
+    /// Number of queued files including the one currently playing
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// The file currently playing
+    pub fn current(&self) -> Option<&Path> {
+        self.queue.front().map(|file| file.path.as_path())
+    }
+
+    /// Playback position in the current file
+    pub fn position(&self) -> Duration {
+        if self.queue.is_empty() { Duration::ZERO } else { self.sink.get_pos() }
+    }
+
+    /// Total duration of the current file if known
+    pub fn duration(&self) -> Option<Duration> {
+        self.queue.front().and_then(|file| file.duration)
+    }
+
+    /// Jumps to a position in the current file. The listener is notified so that it can drop
+    /// state derived from the samples before the jump.
+    pub fn seek(&self, pos: Duration) -> Result<()> {
+        let pos = match self.duration() {
+            Some(duration) => pos.min(duration),
+            None => pos,
+        };
+        self.sink.try_seek(pos).map_err(|e| eyre!("Failed to seek: {}", e))?;
+
+        if let Some(listener) = &self.listener {
+            listener.lock().unwrap().on_seek();
+        }
+
         Ok(())
     }
 
@@ -145,9 +220,16 @@ impl Playback {
         self.sink.play();
     }
 
-    /// Stops playback
-    pub fn stop(&self) {
+    /// Jumps forward (positive offset) or backward (negative offset) in the current file
+    pub fn seek_by(&self, seconds: f32) -> Result<()> {
+        let pos = self.position().as_secs_f32() + seconds;
+        self.seek(Duration::from_secs_f32(pos.max(0.)))
+    }
+
+    /// Stops playback and clears the queue
+    pub fn stop(&mut self) {
         self.sink.stop();
+        self.queue.clear();
     }
 
     /// Returns true if playback is paused
@@ -0,0 +1,434 @@
+use crate::utils::Minstd;
+use eyre::{Result, bail, eyre};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// File extensions of audio files picked up when scanning directories
+pub const AUDIO_EXTENSIONS: [&str; 4] = ["wav", "mp3", "flac", "ogg"];
+
+/// What happens when the end of the playlist is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatMode {
+    /// Stop after the last track
+    Off,
+
+    /// Start again with the first track
+    #[default]
+    All,
+
+    /// Repeat the current track forever
+    One,
+}
+
+/// An ordered list of tracks with shuffle and repeat support
+#[derive(Debug, Clone)]
+pub struct Playlist {
+    tracks: Vec<PathBuf>,
+
+    /// Play order as indices into `tracks`
+    order: Vec<usize>,
+
+    /// Position of the current track in `order`. Equal to `order.len()` when playback finished.
+    pos: usize,
+
+    shuffle: bool,
+    repeat: RepeatMode,
+    rand: Minstd,
+}
+
+impl Playlist {
+    pub fn new(tracks: Vec<PathBuf>, seed: u64) -> Self {
+        Self {
+            order: (0..tracks.len()).collect(),
+            tracks,
+            pos: 0,
+            shuffle: false,
+            repeat: RepeatMode::default(),
+            rand: Minstd::from_seed(seed),
+        }
+    }
+
+    /// Creates a playlist from audio files, directories which are scanned recursively for audio
+    /// files, and `.m3u`/`.pls` playlist files.
+    pub fn load<P: AsRef<Path>>(paths: &[P], seed: u64) -> Result<Self> {
+        let mut tracks = Vec::new();
+        for path in paths {
+            collect_tracks(path.as_ref(), &mut tracks, &mut HashSet::new())?;
+        }
+        if tracks.is_empty() {
+            bail!("No playable audio files found");
+        }
+        Ok(Self::new(tracks, seed))
+    }
+
+    pub fn tracks(&self) -> &[PathBuf] {
+        &self.tracks
+    }
+
+    /// Index into [Self::tracks] of the current track
+    pub fn current_index(&self) -> Option<usize> {
+        self.order.get(self.pos).copied()
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.current_index().map(|i| self.tracks[i].as_path())
+    }
+
+    /// The track which will be played after the current one finished
+    pub fn peek_next(&self) -> Option<&Path> {
+        self.next_pos().map(|pos| self.tracks[self.order[pos]].as_path())
+    }
+
+    /// Moves to the track which follows the current one after it finished playing
+    pub fn advance(&mut self) -> Option<&Path> {
+        self.pos = self.next_pos().unwrap_or(self.order.len());
+        self.current()
+    }
+
+    /// Skips to the next track. Unlike [Self::advance] this leaves a track on repeat.
+    pub fn skip_forward(&mut self) -> Option<&Path> {
+        if self.order.is_empty() {
+            return None;
+        }
+        self.pos = match self.repeat {
+            RepeatMode::Off => (self.pos + 1).min(self.order.len()),
+            RepeatMode::All | RepeatMode::One => (self.pos + 1) % self.order.len(),
+        };
+        self.current()
+    }
+
+    /// Goes back to the previous track
+    pub fn skip_back(&mut self) -> Option<&Path> {
+        if self.order.is_empty() {
+            return None;
+        }
+        self.pos = match self.repeat {
+            RepeatMode::Off => self.pos.saturating_sub(1),
+            RepeatMode::All | RepeatMode::One => {
+                (self.pos.min(self.order.len()) + self.order.len() - 1) % self.order.len()
+            }
+        };
+        self.current()
+    }
+
+    /// Makes the given track the current one
+    pub fn jump_to(&mut self, track: usize) -> Option<&Path> {
+        self.pos = self.order.iter().position(|&i| i == track).unwrap_or(self.order.len());
+        self.current()
+    }
+
+    pub fn shuffle(&self) -> bool {
+        self.shuffle
+    }
+
+    /// Enables or disables shuffle. The current track stays current.
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle == self.shuffle {
+            return;
+        }
+        self.shuffle = shuffle;
+
+        let current = self.current_index();
+
+        self.order = (0..self.tracks.len()).collect();
+        if shuffle {
+            // Fisher-Yates
+            for i in (1..self.order.len()).rev() {
+                let j = self.rand.next_idx(i as u32 + 1) as usize;
+                self.order.swap(i, j);
+            }
+        }
+
+        // move the current track to the front so that all other tracks follow
+        if let Some(current) = current {
+            let pos = self.order.iter().position(|&i| i == current).unwrap();
+            if shuffle {
+                self.order.swap(0, pos);
+                self.pos = 0;
+            } else {
+                self.pos = pos;
+            }
+        }
+    }
+
+    pub fn repeat(&self) -> RepeatMode {
+        self.repeat
+    }
+
+    pub fn set_repeat(&mut self, repeat: RepeatMode) {
+        self.repeat = repeat;
+    }
+
+    fn next_pos(&self) -> Option<usize> {
+        if self.pos >= self.order.len() {
+            return None;
+        }
+        match self.repeat {
+            RepeatMode::Off => (self.pos + 1 < self.order.len()).then_some(self.pos + 1),
+            RepeatMode::All => Some((self.pos + 1) % self.order.len()),
+            RepeatMode::One => Some(self.pos),
+        }
+    }
+}
+
+/// Adds the tracks of a file or directory. `visiting` holds the canonical paths of the playlist
+/// files which are being read, to detect playlists which include themselves.
+fn collect_tracks(
+    path: &Path,
+    tracks: &mut Vec<PathBuf>,
+    visiting: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    if path.is_dir() {
+        return scan_dir(path, tracks);
+    }
+
+    if !path.exists() {
+        bail!("File not found: {path:?}");
+    }
+
+    let entries = match extension(path).as_deref() {
+        Some("m3u") | Some("m3u8") => read_m3u(path)?,
+        Some("pls") => read_pls(path)?,
+        _ => {
+            tracks.push(path.to_path_buf());
+            return Ok(());
+        }
+    };
+
+    let canonical = fs::canonicalize(path).map_err(|e| eyre!("Failed to resolve {path:?}: {e}"))?;
+    if !visiting.insert(canonical.clone()) {
+        bail!("Playlist {path:?} includes itself");
+    }
+    for entry in entries {
+        collect_playlist_entry(path, &entry, tracks, visiting)?;
+    }
+    visiting.remove(&canonical);
+
+    Ok(())
+}
+
+/// Adds an entry of a playlist file. Relative entries are relative to the playlist file.
+fn collect_playlist_entry(
+    playlist: &Path,
+    entry: &str,
+    tracks: &mut Vec<PathBuf>,
+    visiting: &mut HashSet<PathBuf>,
+) -> Result<()> {
+    if entry.contains("://") && !entry.starts_with("file://") {
+        log::warn!("Skipping stream {entry} in {playlist:?}");
+        return Ok(());
+    }
+
+    let entry = Path::new(entry.trim_start_matches("file://"));
+    let path = match playlist.parent() {
+        Some(dir) if entry.is_relative() => dir.join(entry),
+        _ => entry.to_path_buf(),
+    };
+
+    collect_tracks(&path, tracks, visiting).map_err(|e| eyre!("{e} (in playlist {playlist:?})"))
+}
+
+/// Recursively collects all audio files in a directory in alphabetical order
+fn scan_dir(dir: &Path, tracks: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .map_err(|e| eyre!("Failed to read directory {dir:?}: {e}"))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            scan_dir(&path, tracks)?;
+        } else if extension(&path).is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.as_str())) {
+            tracks.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Entries of an M3U playlist: one path per line, lines starting with `#` are comments
+fn read_m3u(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).map_err(|e| eyre!("Failed to read {path:?}: {e}"))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Entries of a PLS playlist: `FileN=path` lines ordered by N
+fn read_pls(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path).map_err(|e| eyre!("Failed to read {path:?}: {e}"))?;
+    let mut entries = text
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.trim().split_once('=')?;
+            let n = key.trim().strip_prefix("File")?.parse::<u32>().ok()?;
+            Some((n, value.trim().to_string()))
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(n, _)| *n);
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    fn playlist(n: usize) -> Playlist {
+        Playlist::new((0..n).map(|i| PathBuf::from(format!("{i}.mp3"))).collect(), 1)
+    }
+
+    fn name(track: Option<&Path>) -> Option<String> {
+        track.map(|p| p.to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn scan_dir_is_recursive_sorted_and_filtered() {
+        let dir = TempDir::new("playlist-scan");
+        let b = dir.file("b.MP3", "");
+        let a = dir.file("a.wav", "");
+        dir.file("cover.jpg", "");
+        let nested = dir.file("sub/c.flac", "");
+
+        let playlist = Playlist::load(&[&dir.0], 0).unwrap();
+        assert_eq!(playlist.tracks(), [a, b, nested]);
+    }
+
+    #[test]
+    fn m3u_skips_comments_and_resolves_relative_paths() {
+        let dir = TempDir::new("playlist-m3u");
+        let a = dir.file("music/a.mp3", "");
+        let b = dir.file("b.ogg", "");
+        let m3u = dir.file(
+            "lists/list.m3u",
+            &format!(
+                "#EXTM3U\n#EXTINF:123,Artist - A\n../music/a.mp3\n\n  \nfile://{}\nhttp://radio/stream\n",
+                b.display()
+            ),
+        );
+
+        let playlist = Playlist::load(&[m3u], 0).unwrap();
+        assert_eq!(playlist.tracks(), [dir.0.join("lists/../music/a.mp3"), b]);
+        assert_eq!(fs::canonicalize(&playlist.tracks()[0]).unwrap(), fs::canonicalize(a).unwrap());
+    }
+
+    #[test]
+    fn pls_orders_entries_by_number() {
+        let dir = TempDir::new("playlist-pls");
+        let a = dir.file("a.mp3", "");
+        let b = dir.file("b.mp3", "");
+        let pls = dir.file(
+            "list.pls",
+            "[playlist]\nFile2=b.mp3\nTitle2=B\nFile1 = a.mp3\nLength1=-1\nNumberOfEntries=2\n",
+        );
+
+        let playlist = Playlist::load(&[pls], 0).unwrap();
+        assert_eq!(playlist.tracks(), [a, b]);
+    }
+
+    #[test]
+    fn missing_files_are_errors() {
+        let dir = TempDir::new("playlist-missing");
+        let m3u = dir.file("list.m3u", "missing.mp3\n");
+        let err = Playlist::load(&[&m3u], 0).unwrap_err().to_string();
+        assert!(err.contains("missing.mp3") && err.contains("list.m3u"), "{err}");
+
+        let empty = dir.file("empty/list.m3u", "# nothing\n");
+        let err = Playlist::load(&[empty], 0).unwrap_err().to_string();
+        assert!(err.contains("No playable audio files"), "{err}");
+    }
+
+    #[test]
+    fn playlist_cycles_are_errors() {
+        let dir = TempDir::new("playlist-cycle");
+        let own = dir.file("own.m3u", "own.m3u\n");
+        let err = Playlist::load(&[own], 0).unwrap_err().to_string();
+        assert!(err.contains("includes itself"), "{err}");
+
+        dir.file("a.mp3", "");
+        let a = dir.file("a.m3u", "a.mp3\nsub/b.pls\n");
+        dir.file("sub/b.pls", "[playlist]\nFile1=../a.m3u\n");
+        let err = Playlist::load(&[a], 0).unwrap_err().to_string();
+        assert!(err.contains("includes itself") && err.contains("b.pls"), "{err}");
+
+        // the same playlist twice is no cycle
+        let twice = dir.file("twice.m3u", "b.m3u\nb.m3u\n");
+        dir.file("b.m3u", "a.mp3\n");
+        assert_eq!(Playlist::load(&[twice], 0).unwrap().tracks().len(), 2);
+    }
+
+    #[test]
+    fn empty_playlist_has_no_tracks() {
+        let mut playlist = playlist(0);
+        assert_eq!(playlist.current(), None);
+        assert_eq!(playlist.peek_next(), None);
+        assert_eq!(playlist.advance(), None);
+        assert_eq!(playlist.skip_forward(), None);
+        assert_eq!(playlist.skip_back(), None);
+    }
+
+    #[test]
+    fn repeat_off_stops_after_last_track() {
+        let mut playlist = playlist(2);
+        playlist.set_repeat(RepeatMode::Off);
+        assert_eq!(name(playlist.advance()).as_deref(), Some("1.mp3"));
+        assert_eq!(playlist.peek_next(), None);
+        assert_eq!(playlist.advance(), None);
+        assert_eq!(playlist.advance(), None);
+        assert_eq!(name(playlist.skip_back()).as_deref(), Some("1.mp3"));
+        assert_eq!(name(playlist.skip_back()).as_deref(), Some("0.mp3"));
+        assert_eq!(name(playlist.skip_back()).as_deref(), Some("0.mp3"));
+    }
+
+    #[test]
+    fn repeat_all_wraps_around() {
+        let mut playlist = playlist(3);
+        assert_eq!(name(playlist.skip_back()).as_deref(), Some("2.mp3"));
+        assert_eq!(name(playlist.advance()).as_deref(), Some("0.mp3"));
+        assert_eq!(name(playlist.skip_forward()).as_deref(), Some("1.mp3"));
+    }
+
+    #[test]
+    fn repeat_one_repeats_the_last_track() {
+        let mut playlist = playlist(2);
+        playlist.set_repeat(RepeatMode::One);
+        playlist.jump_to(1);
+        assert_eq!(name(playlist.peek_next()).as_deref(), Some("1.mp3"));
+        assert_eq!(name(playlist.advance()).as_deref(), Some("1.mp3"));
+        // skipping leaves the repeated track and wraps around
+        assert_eq!(name(playlist.skip_forward()).as_deref(), Some("0.mp3"));
+    }
+
+    #[test]
+    fn shuffle_keeps_the_current_track() {
+        let mut playlist = playlist(20);
+        playlist.jump_to(7);
+        playlist.set_shuffle(true);
+        assert_eq!(playlist.current_index(), Some(7));
+
+        let mut order = vec![7];
+        for _ in 1..20 {
+            playlist.advance();
+            order.push(playlist.current_index().unwrap());
+        }
+        assert_ne!(order, (0..20).collect::<Vec<_>>());
+        order.sort();
+        assert_eq!(order, (0..20).collect::<Vec<_>>());
+
+        playlist.set_shuffle(false);
+        let current = playlist.current_index().unwrap();
+        playlist.advance();
+        assert_eq!(playlist.current_index(), Some((current + 1) % 20));
+    }
+}
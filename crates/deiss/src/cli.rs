@@ -0,0 +1,504 @@
+use crate::{
+    audio::{Playlist, RepeatMode},
+    config::{AudioInput, Config},
+    headless::{BenchConfig, FrameFormat},
+    painter::{ModeId, SwitchPolicyKind, TransitionCurve, WaveformId},
+    profile::DEFAULT_PROFILE,
+    renderer::CrtShaderSettings,
+    utils::Shape2,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use eyre::Result;
+use std::{
+    ops::RangeInclusive,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// DEISS audio visualizer
+#[derive(Debug, Parser)]
+#[command(name = "deiss", version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Arguments for `play` when no subcommand is given
+    #[command(flatten)]
+    pub play: PlayArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Play audio files and visualize them in a window
+    Play(PlayArgs),
+
+    /// Visualize live audio from an input device such as a microphone, line-in or output monitor
+    Listen(ListenArgs),
+
+    /// Render an audio file offline into a sequence of frames
+    Render(RenderArgs),
+
+    /// Print the tempo detected in an audio file over time
+    Tempo(TempoArgs),
+
+    /// Measure warp map baking and processing and check the parallel versions against the scalar
+    /// ones
+    Bench(BenchArgs),
+
+    /// List all available modes
+    ListModes(ListModesArgs),
+
+    /// List all audio input devices
+    ListDevices,
+}
+
+impl Cli {
+    /// Returns the subcommand treating a missing subcommand as `play`
+    pub fn into_command(self) -> Command {
+        self.command.unwrap_or(Command::Play(self.play))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct PlayArgs {
+    /// Audio files, directories or .m3u/.pls playlists to play
+    pub files: Vec<String>,
+
+    /// Play tracks in random order
+    #[arg(long)]
+    pub shuffle: bool,
+
+    /// What to do at the end of the playlist
+    #[arg(long, value_enum, default_value_t = RepeatArg::All)]
+    pub repeat: RepeatArg,
+
+    #[command(flatten)]
+    pub visual: VisualArgs,
+
+    #[command(flatten)]
+    pub window: WindowArgs,
+
+    /// Playback volume (0.0 to 2.0)
+    #[arg(long, default_value_t = 1.0, value_parser = f32_in(0.0..=2.0))]
+    pub volume: f32,
+}
+
+#[derive(Debug, Args)]
+pub struct ListenArgs {
+    /// Name of the input device to record from (see `list-devices`). Defaults to the default input
+    /// device. Use the `pulse` or `pipewire` device to record what is playing on the system.
+    #[arg(long)]
+    pub device: Option<String>,
+
+    #[command(flatten)]
+    pub visual: VisualArgs,
+
+    #[command(flatten)]
+    pub window: WindowArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct RenderArgs {
+    /// Audio file to render
+    pub file: String,
+
+    /// Output directory for frame files (ignored for raw output)
+    #[arg(long, short, default_value = "frames")]
+    pub out: PathBuf,
+
+    /// Output format of frames
+    #[arg(long, value_enum, default_value_t = FormatArg::Png)]
+    pub format: FormatArg,
+
+    /// Frames per second of audio
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..))]
+    pub fps: u32,
+
+    /// Stop after this many frames
+    #[arg(long)]
+    pub max_frames: Option<u64>,
+
+    #[command(flatten)]
+    pub visual: VisualArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArgs {
+    /// Internal resolution as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_resolution, default_value = "1920x1080")]
+    pub resolution: Shape2,
+
+    /// Only measure this mode. Can be given multiple times. Defaults to all modes.
+    #[arg(long)]
+    pub mode: Vec<u32>,
+
+    /// Number of times each warp map is applied
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+
+    /// Seed for the random number generator
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Directory with additional mode presets (*.toml). Can be given multiple times.
+    #[arg(long = "presets", value_name = "DIR")]
+    pub preset_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ListModesArgs {
+    /// Directory with additional mode presets (*.toml). Can be given multiple times.
+    #[arg(long = "presets", value_name = "DIR")]
+    pub preset_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct TempoArgs {
+    /// Audio file to analyze
+    pub file: String,
+
+    /// Seconds between printed lines
+    #[arg(long, default_value_t = 1.0, value_parser = f32_in(0.01..=f32::INFINITY))]
+    pub interval: f32,
+}
+
+/// Options shared by interactive and offline rendering
+#[derive(Debug, Args)]
+pub struct VisualArgs {
+    /// Internal resolution as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_resolution, default_value = "640x480")]
+    pub resolution: Shape2,
+
+    /// Always use this mode
+    #[arg(long)]
+    pub mode: Option<u32>,
+
+    /// Always use this waveform (1-7)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=7))]
+    pub waveform: Option<u32>,
+
+    /// Seed for the random number generator
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// Directory with additional mode presets (*.toml). Can be given multiple times.
+    #[arg(long = "presets", value_name = "DIR")]
+    pub preset_dirs: Vec<PathBuf>,
+
+    /// Settings profile to load. Changes made in the window are saved to it. `play` and `listen`
+    /// use the profile `default` if none is given.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Number of frames over which warp maps cross-fade on mode switches (0 = instant)
+    #[arg(long)]
+    pub transition_frames: Option<u32>,
+
+    /// Shape of the cross-fade between warp maps
+    #[arg(long, value_enum)]
+    pub transition_curve: Option<CurveArg>,
+
+    /// When to switch to the next mode
+    #[arg(long, value_enum)]
+    pub switch: Option<SwitchArg>,
+
+    /// Minimum time between mode switches in seconds
+    #[arg(long, value_parser = f32_in(0.5..=f32::INFINITY))]
+    pub switch_interval: Option<f32>,
+
+    /// Maximum time between mode switches in seconds (random, beat and section switching)
+    #[arg(long, value_parser = f32_in(0.5..=f32::INFINITY))]
+    pub switch_max_interval: Option<f32>,
+
+    /// Beats per bar for beat switching. Modes only switch on the first beat of a bar.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub beats_per_bar: Option<u32>,
+
+    /// How much the image zooms in on kicks (0.0 = off, 0.05 = noticeable, at most 0.5)
+    #[arg(long, value_parser = f32_in(0.0..=0.5))]
+    pub zoom_pulse: Option<f32>,
+}
+
+/// Options of the interactive window
+#[derive(Debug, Args)]
+pub struct WindowArgs {
+    /// Initial window size as WIDTHxHEIGHT in logical pixels
+    #[arg(long, value_parser = parse_resolution, default_value = "1920x1440")]
+    pub window_size: Shape2,
+
+    /// Start in borderless fullscreen mode
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Adapt the aspect ratio of the internal resolution to the window, keeping its number of
+    /// pixels
+    #[arg(long)]
+    pub match_window: bool,
+
+    #[command(flatten)]
+    pub crt: CrtArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct CrtArgs {
+    /// Disable CRT screen warp
+    #[arg(long)]
+    pub no_warp: bool,
+
+    /// Strength of CRT screen warp (0.0 to 1.0)
+    #[arg(long, value_parser = f32_in(0.0..=1.0))]
+    pub warp_strength: Option<f32>,
+
+    /// Disable CRT scanlines
+    #[arg(long)]
+    pub no_scanlines: bool,
+
+    /// Strength of CRT scanlines (0.0 to 1.0)
+    #[arg(long, value_parser = f32_in(0.0..=1.0))]
+    pub scanline_strength: Option<f32>,
+
+    /// Disable CRT afterglow
+    #[arg(long)]
+    pub no_afterglow: bool,
+
+    /// Amount of CRT afterglow (0.0 to 1.0)
+    #[arg(long, value_parser = f32_in(0.0..=1.0))]
+    pub afterglow: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum FormatArg {
+    Png,
+    Ppm,
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CurveArg {
+    Linear,
+    Smooth,
+    EaseIn,
+    EaseOut,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SwitchArg {
+    /// Every few seconds like the original
+    Fixed,
+    /// After a random time
+    Random,
+    /// On a beat
+    Beat,
+    /// When the music changes a lot
+    Section,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum RepeatArg {
+    Off,
+    All,
+    One,
+}
+
+impl From<RepeatArg> for RepeatMode {
+    fn from(repeat: RepeatArg) -> Self {
+        match repeat {
+            RepeatArg::Off => RepeatMode::Off,
+            RepeatArg::All => RepeatMode::All,
+            RepeatArg::One => RepeatMode::One,
+        }
+    }
+}
+
+impl From<CurveArg> for TransitionCurve {
+    fn from(curve: CurveArg) -> Self {
+        match curve {
+            CurveArg::Linear => TransitionCurve::Linear,
+            CurveArg::Smooth => TransitionCurve::SmoothStep,
+            CurveArg::EaseIn => TransitionCurve::EaseIn,
+            CurveArg::EaseOut => TransitionCurve::EaseOut,
+        }
+    }
+}
+
+impl From<SwitchArg> for SwitchPolicyKind {
+    fn from(switch: SwitchArg) -> Self {
+        match switch {
+            SwitchArg::Fixed => SwitchPolicyKind::Fixed,
+            SwitchArg::Random => SwitchPolicyKind::Random,
+            SwitchArg::Beat => SwitchPolicyKind::Beat,
+            SwitchArg::Section => SwitchPolicyKind::Section,
+        }
+    }
+}
+
+impl From<FormatArg> for FrameFormat {
+    fn from(format: FormatArg) -> Self {
+        match format {
+            FormatArg::Png => FrameFormat::Png,
+            FormatArg::Ppm => FrameFormat::Ppm,
+            FormatArg::Raw => FrameFormat::Raw,
+        }
+    }
+}
+
+impl VisualArgs {
+    fn apply(&self, config: &mut Config) {
+        config.paint_shape = self.resolution;
+        if let Some(mode) = self.mode {
+            config.mode = Some(ModeId(mode));
+        }
+        if let Some(waveform) = self.waveform {
+            config.waveform = Some(WaveformId(waveform));
+        }
+        config.seed = self.seed;
+        config.preset_dirs.extend(self.preset_dirs.iter().cloned());
+        if let Some(frames) = self.transition_frames {
+            config.transition.frames = frames;
+        }
+        if let Some(curve) = self.transition_curve {
+            config.transition.curve = curve.into();
+        }
+        if let Some(switch) = self.switch {
+            config.switch.policy = switch.into();
+        }
+        if let Some(interval) = self.switch_interval {
+            config.switch.min_interval = interval;
+        }
+        if let Some(interval) = self.switch_max_interval {
+            config.switch.max_interval = interval;
+        }
+        if let Some(beats) = self.beats_per_bar {
+            config.switch.beats_per_bar = beats;
+        }
+        if let Some(zoom) = self.zoom_pulse {
+            config.dynamic.zoom_pulse = zoom;
+        }
+    }
+}
+
+impl WindowArgs {
+    fn apply(&self, config: &mut Config) {
+        config.window_size = (self.window_size.cols(), self.window_size.rows());
+        config.fullscreen = self.fullscreen;
+        config.match_window = self.match_window;
+        self.crt.apply(&mut config.crt_shader_settings);
+    }
+}
+
+impl CrtArgs {
+    fn apply(&self, settings: &mut CrtShaderSettings) {
+        settings.warp_enabled &= !self.no_warp;
+        settings.scanlines_enabled &= !self.no_scanlines;
+        settings.afterglow_enabled &= !self.no_afterglow;
+        if let Some(v) = self.warp_strength {
+            settings.warp_strength = v;
+        }
+        if let Some(v) = self.scanline_strength {
+            settings.scanline_strength = v;
+        }
+        if let Some(v) = self.afterglow {
+            settings.afterglow = v;
+        }
+    }
+}
+
+impl PlayArgs {
+    /// Loads all given files into a playlist
+    pub fn playlist(&self) -> Result<Playlist> {
+        let seed = self.visual.seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64
+        });
+        let mut playlist = Playlist::load(&self.files, seed)?;
+        playlist.set_repeat(self.repeat.into());
+        playlist.set_shuffle(self.shuffle);
+        Ok(playlist)
+    }
+}
+
+impl TryFrom<&PlayArgs> for Config {
+    type Error = eyre::Report;
+
+    fn try_from(args: &PlayArgs) -> Result<Self> {
+        let mut config = Config { volume: args.volume, ..Default::default() };
+        config.load_profile(args.visual.profile.as_deref().unwrap_or(DEFAULT_PROFILE))?;
+        args.visual.apply(&mut config);
+        args.window.apply(&mut config);
+        Ok(config)
+    }
+}
+
+impl TryFrom<&ListenArgs> for Config {
+    type Error = eyre::Report;
+
+    fn try_from(args: &ListenArgs) -> Result<Self> {
+        let mut config = Config {
+            input: AudioInput::Capture { device: args.device.clone() },
+            ..Default::default()
+        };
+        config.load_profile(args.visual.profile.as_deref().unwrap_or(DEFAULT_PROFILE))?;
+        args.visual.apply(&mut config);
+        args.window.apply(&mut config);
+        Ok(config)
+    }
+}
+
+impl TryFrom<&RenderArgs> for Config {
+    type Error = eyre::Report;
+
+    /// Renders only use a profile if one is given so that they don't depend on the last session
+    fn try_from(args: &RenderArgs) -> Result<Self> {
+        let mut config = Config {
+            playlist: Playlist::new(vec![PathBuf::from(&args.file)], 0),
+            ..Default::default()
+        };
+        if let Some(name) = &args.visual.profile {
+            config.load_profile(name)?;
+        }
+        args.visual.apply(&mut config);
+        Ok(config)
+    }
+}
+
+impl From<&BenchArgs> for Config {
+    fn from(args: &BenchArgs) -> Self {
+        let mut config =
+            Config { paint_shape: args.resolution, seed: args.seed, ..Default::default() };
+        config.preset_dirs.extend(args.preset_dirs.iter().cloned());
+        config
+    }
+}
+
+impl From<&BenchArgs> for BenchConfig {
+    fn from(args: &BenchArgs) -> Self {
+        BenchConfig {
+            modes: args.mode.iter().copied().map(ModeId).collect(),
+            iterations: args.iterations,
+        }
+    }
+}
+
+/// Parses a resolution given as WIDTHxHEIGHT
+fn parse_resolution(s: &str) -> Result<Shape2, String> {
+    let (w, h) = s.split_once('x').ok_or_else(|| format!("expected WIDTHxHEIGHT, got '{s}'"))?;
+    let w = w.parse::<u32>().map_err(|e| format!("invalid width '{w}': {e}"))?;
+    let h = h.parse::<u32>().map_err(|e| format!("invalid height '{h}': {e}"))?;
+    if w < 320 || h < 240 {
+        return Err(format!("resolution must be at least 320x240, got '{s}'"));
+    }
+    Ok((h, w).into())
+}
+
+/// Parser for a number in the given range
+fn f32_in(range: RangeInclusive<f32>) -> impl Fn(&str) -> Result<f32, String> + Clone {
+    move |s| {
+        let value = s.parse::<f32>().map_err(|e| format!("invalid number '{s}': {e}"))?;
+        if range.contains(&value) {
+            Ok(value)
+        } else if range.end().is_infinite() {
+            Err(format!("must be at least {}, got {value}", range.start()))
+        } else {
+            Err(format!("must be between {} and {}, got {value}", range.start(), range.end()))
+        }
+    }
+}
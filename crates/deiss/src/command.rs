@@ -0,0 +1,134 @@
+use crate::utils::Shape2;
+use std::time::Duration;
+use winit::keyboard::{Key, NamedKey};
+
+/// Actions requested by the user which are executed by the app
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Skip to the next track in the playlist
+    SkipForward,
+
+    /// Go back to the previous track in the playlist
+    SkipBack,
+
+    /// Play the track with the given index in the playlist
+    JumpToTrack(usize),
+
+    /// Stop capturing and continue with the playlist
+    PlayPlaylist,
+
+    /// Record from the input device with the given name or the default input device
+    Capture(Option<String>),
+
+    /// Query the list of input devices again
+    RefreshInputDevices,
+
+    /// Pause or resume playback
+    TogglePause,
+
+    /// Jump to a position in the current track
+    Seek(Duration),
+
+    /// Jump forward or backward by the given number of seconds
+    SeekBy(f32),
+
+    /// Set the playback volume (0.0 to 1.0+)
+    SetVolume(f32),
+
+    /// Change the playback volume by the given amount
+    ChangeVolume(f32),
+
+    /// Switch to the next mode
+    NextMode,
+
+    /// Switch to the previous mode
+    PreviousMode,
+
+    /// Always pick the current mode or release a locked mode
+    ToggleModeLock,
+
+    /// Show or hide the GUI
+    ToggleGui,
+
+    /// Switch between window and borderless fullscreen
+    ToggleFullscreen,
+
+    /// Leave fullscreen
+    ExitFullscreen,
+
+    /// Save the current image as PNG, and the frame after the CRT effect if `crt` is set
+    Screenshot { crt: bool },
+
+    /// Change the internal resolution
+    SetResolution(Shape2),
+
+    /// Adapt the aspect ratio of the internal resolution to the window or not
+    SetMatchWindow(bool),
+
+    /// Switch to the profile with the given name and apply its settings
+    LoadProfile(String),
+
+    /// Save the current settings as a new profile and switch to it
+    SaveProfileAs(String),
+
+    /// Bookmark the look which is shown under the given name or a generated one
+    AddBookmark(Option<String>),
+
+    /// Show the bookmark with the given index
+    RecallBookmark(usize),
+
+    /// Delete the bookmark with the given index
+    RemoveBookmark(usize),
+}
+
+/// Keyboard shortcuts as (key, description) for display in the GUI
+pub const SHORTCUTS: [(&str, &str); 12] = [
+    ("Space", "Pause / resume"),
+    ("← / →", "Seek -/+ 10 s"),
+    ("↑ / ↓", "Volume up / down"),
+    ("N / P", "Next / previous track"),
+    ("M / Shift+M", "Next / previous mode"),
+    ("L", "Lock mode"),
+    ("B", "Bookmark the current look"),
+    ("G", "Show / hide GUI"),
+    ("F / F11", "Fullscreen"),
+    ("Esc", "Leave fullscreen"),
+    ("S / F12", "Screenshot"),
+    ("Shift+S", "Screenshot with CRT frame"),
+];
+
+/// Seconds to jump when seeking with the keyboard
+const SEEK_STEP: f32 = 10.;
+
+/// Volume change per key press
+const VOLUME_STEP: f32 = 0.1;
+
+impl Command {
+    /// Maps a pressed key to its command
+    pub fn from_key(key: &Key, shift: bool) -> Option<Self> {
+        let command = match key {
+            Key::Named(NamedKey::Space) => Command::TogglePause,
+            Key::Named(NamedKey::ArrowLeft) => Command::SeekBy(-SEEK_STEP),
+            Key::Named(NamedKey::ArrowRight) => Command::SeekBy(SEEK_STEP),
+            Key::Named(NamedKey::ArrowUp) => Command::ChangeVolume(VOLUME_STEP),
+            Key::Named(NamedKey::ArrowDown) => Command::ChangeVolume(-VOLUME_STEP),
+            Key::Named(NamedKey::Escape) => Command::ExitFullscreen,
+            Key::Named(NamedKey::F11) => Command::ToggleFullscreen,
+            Key::Named(NamedKey::F12) => Command::Screenshot { crt: false },
+            Key::Character(c) => match c.to_lowercase().as_str() {
+                "n" => Command::SkipForward,
+                "p" => Command::SkipBack,
+                "m" if shift => Command::PreviousMode,
+                "m" => Command::NextMode,
+                "l" => Command::ToggleModeLock,
+                "b" => Command::AddBookmark(None),
+                "g" => Command::ToggleGui,
+                "f" => Command::ToggleFullscreen,
+                "s" => Command::Screenshot { crt: shift },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(command)
+    }
+}
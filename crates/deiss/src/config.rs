@@ -1,8 +1,154 @@
-use std::sync::{Arc, Mutex};
+use crate::{
+    audio::Playlist,
+    painter::{
+        DynamicWarpSettings, ModeId, Settings, SwitchSettings, TransitionSettings, WaveformId,
+    },
+    profile::{Profile, profile_path},
+    renderer::CrtShaderSettings,
+    utils::Shape2,
+};
+use eyre::Result;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+/// Where the visualized audio comes from
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum AudioInput {
+    /// Play the files of the playlist
+    #[default]
+    Playlist,
+
+    /// Record from an input device. Uses the default input device if no name is given.
+    Capture { device: Option<String> },
+}
 
 #[derive(Clone)]
 pub struct Config {
-    pub filename: String,
+    /// Source of the visualized audio
+    pub input: AudioInput,
+
+    /// Audio files to play
+    pub playlist: Playlist,
+
+    /// Shape of the internal paint buffer
+    pub paint_shape: Shape2,
+
+    /// Initial logical window size (width, height)
+    pub window_size: (u32, u32),
+
+    /// Start in borderless fullscreen mode
+    pub fullscreen: bool,
+
+    /// Adapt the aspect ratio of the paint buffer to the window, keeping its number of pixels
+    pub match_window: bool,
+
+    /// Seed for the random number generator
+    pub seed: Option<u64>,
+
+    /// Playback volume (0.0 to 1.0+)
+    pub volume: f32,
+
+    /// If set always pick this mode
+    pub mode: Option<ModeId>,
+
+    /// If set always pick this waveform
+    pub waveform: Option<WaveformId>,
+
+    /// Cross-fade between warp maps on mode switches
+    pub transition: TransitionSettings,
+
+    /// When to switch modes
+    pub switch: SwitchSettings,
+
+    /// Audio-reactive modulation of warp maps
+    pub dynamic: DynamicWarpSettings,
+
+    /// Directories with mode presets which are added to the built-in modes
+    pub preset_dirs: Vec<PathBuf>,
+
+    /// Favorites file with bookmarked looks
+    pub bookmarks_path: Option<PathBuf>,
+
+    pub crt_shader_settings: CrtShaderSettings,
+
+    /// Name of the profile which receives the changes made in the window. Nothing is saved if not
+    /// set.
+    pub profile_name: Option<String>,
+
+    /// Preferences loaded from the profile
+    pub profile: Profile,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            input: AudioInput::default(),
+            playlist: Playlist::new(Vec::new(), 0),
+            paint_shape: (480, 640).into(),
+            window_size: (3 * 640, 3 * 480),
+            fullscreen: false,
+            match_window: false,
+            seed: None,
+            volume: 1.0,
+            mode: None,
+            waveform: None,
+            transition: TransitionSettings::default(),
+            switch: SwitchSettings::default(),
+            dynamic: DynamicWarpSettings::default(),
+            preset_dirs: default_preset_dir().filter(|dir| dir.is_dir()).into_iter().collect(),
+            bookmarks_path: default_bookmarks_path(),
+            crt_shader_settings: CrtShaderSettings::default(),
+            profile_name: None,
+            profile: Profile::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads a profile. Settings which are changed afterwards, e.g. by command line options,
+    /// override the profile.
+    pub fn load_profile(&mut self, name: &str) -> Result<()> {
+        let profile = Profile::load(&profile_path(name)?)?;
+        self.transition = profile.transition.clone();
+        self.switch = profile.switch.clone();
+        self.dynamic = profile.dynamic.clone();
+        self.crt_shader_settings = profile.crt.clone();
+        self.profile = profile;
+        self.profile_name = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Applies the user preferences stored in the config to painter settings
+    pub fn apply(&self, settings: &mut Settings) {
+        let gf = settings.gf;
+        self.profile.apply(settings);
+        if self.seed.is_some() {
+            // colors generated from a given seed override the profile
+            settings.gf = gf;
+        }
+        if self.mode.is_some() {
+            settings.mode_prefs.set_priority(self.mode);
+        }
+        if self.waveform.is_some() {
+            settings.waveform_prefs.set_priority(self.waveform);
+        }
+        settings.transition = self.transition.clone();
+        settings.switch = self.switch.clone();
+        settings.dynamic = self.dynamic.clone();
+        settings.crt_shader_settings = self.crt_shader_settings.clone();
+    }
+}
+
+/// Directory of user presets, e.g. `~/.config/deiss/presets` on Linux
+pub fn default_preset_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("deiss").join("presets"))
+}
+
+/// Favorites file of the user, e.g. `~/.config/deiss/bookmarks.toml` on Linux
+pub fn default_bookmarks_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("deiss").join("bookmarks.toml"))
 }
 
 #[derive(Clone)]
@@ -1,17 +1,26 @@
 use crate::{
-    audio::Playback,
-    config::{Config, SharedConfig},
-    gui::deiss_gui,
-    painter::Painter,
+    audio::{Capture, Playback, Playlist, input_devices},
+    command::Command,
+    config::{AudioInput, Config, SharedConfig},
+    gui::{AppGuiState, deiss_gui},
+    headless::write_png_with_text,
+    painter::{Clock, Painter, save_bookmarks},
+    profile::{ActiveProfile, DEFAULT_PROFILE, Profile, list_profiles, profile_path},
     renderer::{Gpu, Renderer, Surface},
+    utils::Shape2,
 };
 use eyre::Result;
-use std::sync::{Arc, Mutex};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
+    event::{ElementState, KeyEvent, WindowEvent},
     event_loop::ActiveEventLoop,
-    window::{Window, WindowAttributes, WindowId},
+    keyboard::ModifiersState,
+    window::{Fullscreen, Window, WindowAttributes, WindowId},
 };
 
 pub struct DeissApp {
@@ -25,7 +34,10 @@ impl DeissApp {
     }
 
     fn resumed_impl(&mut self, event_loop: &ActiveEventLoop) -> Result<()> {
-        let window_size = winit::dpi::LogicalSize::new((3 * 640) as f32, (3 * 480) as f32);
+        let config = self.config.lock();
+
+        let window_size =
+            winit::dpi::LogicalSize::new(config.window_size.0 as f32, config.window_size.1 as f32);
 
         // Calculate center position
         let center_position = if let Some(monitor) = event_loop.primary_monitor() {
@@ -47,7 +59,8 @@ impl DeissApp {
                 WindowAttributes::default()
                     .with_title("DEISS")
                     .with_inner_size(window_size)
-                    .with_position(center_position),
+                    .with_position(center_position)
+                    .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None))),
             )?,
         );
 
@@ -73,6 +86,7 @@ impl ApplicationHandler for DeissApp {
         match event {
             WindowEvent::CloseRequested => {
                 log::info!("The close button was pressed; stopping");
+                state.save_profile();
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => {
@@ -82,9 +96,23 @@ impl ApplicationHandler for DeissApp {
             WindowEvent::Resized(size) => {
                 state.resize(size);
             }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                state.modifiers = modifiers.state();
+                let _ = state.handle_input(&event);
+            }
             _ => {
-                // Let GUI handle other events
-                state.handle_input(&event);
+                // Let GUI handle other events. Keys the GUI doesn't need are shortcuts.
+                let response = state.handle_input(&event);
+                if let WindowEvent::KeyboardInput {
+                    event: KeyEvent { logical_key, state: ElementState::Pressed, .. },
+                    ..
+                } = &event
+                    && !response.consumed
+                    && let Some(command) =
+                        Command::from_key(logical_key, state.modifiers.shift_key())
+                {
+                    state.execute(command);
+                }
             }
         }
     }
@@ -95,9 +123,32 @@ struct State {
     window: Arc<Window>,
     surface: Surface,
     renderer: Renderer,
-    config: SharedConfig,
     playback: Playback,
+    playlist: Playlist,
+
+    /// Live capture from an input device. Replaces the playlist while active.
+    capture: Option<Capture>,
+    input_devices: Vec<String>,
+
     painter: Arc<Mutex<Painter>>,
+
+    /// Internal resolution chosen by the user
+    paint_shape: Shape2,
+
+    /// Adapt the aspect ratio of `paint_shape` to the window
+    match_window: bool,
+
+    /// Profile which receives all changes of the settings
+    profile: ActiveProfile,
+
+    /// Names of all saved profiles
+    profiles: Vec<String>,
+
+    /// Favorites file which receives new bookmarks
+    bookmarks_path: Option<PathBuf>,
+
+    show_gui: bool,
+    modifiers: ModifiersState,
 }
 
 impl State {
@@ -105,21 +156,54 @@ impl State {
         let gpu = Arc::new(Gpu::new().await?);
         let surface = Surface::new(gpu.clone(), window.clone())?;
 
-        let shape = (480, 640).into();
+        let cfg = config.lock();
+        let shape = cfg.paint_shape;
 
         let renderer = Renderer::new(&gpu, &window, shape, surface.size_as_shape());
 
-        let painter = Arc::new(Mutex::new(Painter::new(shape)));
+        let mut painter = Painter::new(shape, Clock::realtime(), cfg.seed);
+        painter.load_presets(&cfg.preset_dirs)?;
+        if let Some(path) = &cfg.bookmarks_path {
+            painter.load_bookmarks(path)?;
+        }
+        cfg.apply(&mut painter.settings);
+        let profile_name = cfg.profile_name.clone().unwrap_or_else(|| DEFAULT_PROFILE.into());
+        let profile = ActiveProfile::new(profile_name, cfg.profile.clone(), &painter.settings);
+        let painter = Arc::new(Mutex::new(painter));
 
         // let listener = ConsoleAudioListener::new();
         let mut playback = Playback::new()?;
         playback.set_listener(painter.clone());
+        playback.set_volume(cfg.volume);
 
-        let filename = config.lock().filename;
-        log::info!("Now playing: {filename}");
-        playback.play(&filename)?;
+        let capture = match &cfg.input {
+            AudioInput::Playlist => None,
+            AudioInput::Capture { device } => {
+                Some(Capture::start(device.as_deref(), painter.clone())?)
+            }
+        };
 
-        Ok(Self { gpu, window, surface, renderer, config, playback, painter })
+        let mut state = Self {
+            gpu,
+            window,
+            surface,
+            renderer,
+            playback,
+            playlist: cfg.playlist,
+            capture,
+            input_devices: list_input_devices(),
+            painter,
+            paint_shape: shape,
+            match_window: cfg.match_window,
+            profile,
+            profiles: list_profiles(),
+            bookmarks_path: cfg.bookmarks_path,
+            show_gui: true,
+            modifiers: ModifiersState::default(),
+        };
+        state.update_paint_shape();
+        state.update_playlist();
+        Ok(state)
     }
 
     pub fn window(&self) -> &Window {
@@ -128,12 +212,23 @@ impl State {
 
     pub fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
         self.surface.resize(size);
+        self.update_paint_shape();
+    }
+
+    /// Resizes the painter to the chosen resolution, adapted to the window if requested
+    fn update_paint_shape(&mut self) {
+        let shape = if self.match_window {
+            self.paint_shape.with_aspect_of(self.surface.size_as_shape())
+        } else {
+            self.paint_shape
+        };
+        self.painter.lock().unwrap().resize(shape);
     }
 
     pub fn render(&mut self) {
-        if self.playback.is_empty() {
-            self.playback.play(&self.config.lock().filename).unwrap();
-        }
+        // Playback must be updated before locking the painter: the audio thread locks the painter
+        // too and the sink may wait for it
+        self.update_playlist();
 
         let (surface_texture, texture_view) =
             self.surface.texture().expect("failed to acquire next swapchain texture");
@@ -150,22 +245,336 @@ impl State {
             painter.settings(),
         );
 
-        self.renderer.render_gui(
-            &self.gpu,
-            &texture_view,
-            self.surface.size_as_shape(),
-            &self.window,
-            |ctx| {
-                let p: &mut Painter = &mut painter;
-                deiss_gui(ctx, &mut p.settings, &mut p.globals)
-            },
-        );
+        let mut commands = Vec::new();
+        if self.show_gui {
+            self.renderer.render_gui(
+                &self.gpu,
+                &texture_view,
+                self.surface.size_as_shape(),
+                &self.window,
+                |ctx| {
+                    let p: &mut Painter = &mut painter;
+                    let app = AppGuiState {
+                        playlist: &mut self.playlist,
+                        input_devices: &self.input_devices,
+                        capture_device: self.capture.as_ref().map(Capture::device),
+                        paused: self.playback.is_paused(),
+                        volume: self.playback.volume(),
+                        position: self.playback.position(),
+                        duration: self.playback.duration(),
+                        mode: p.mode(),
+                        paint_shape: self.paint_shape,
+                        match_window: self.match_window,
+                        profile: self.profile.name(),
+                        profiles: &self.profiles,
+                        bookmarks: &p.bookmarks,
+                    };
+                    deiss_gui(ctx, &mut p.settings, &mut p.globals, app, &mut commands)
+                },
+            );
+        }
+        self.profile.update(painter.settings());
+        drop(painter);
 
         self.window.pre_present_notify();
         surface_texture.present();
+
+        for command in commands {
+            self.execute(command);
+        }
+    }
+
+    /// Executes a command requested by the user. Must not be called while the painter is locked.
+    pub fn execute(&mut self, command: Command) {
+        match command {
+            Command::SkipForward => {
+                let track = self.playlist.skip_forward().map(Path::to_path_buf);
+                self.change_track(track);
+            }
+            Command::SkipBack => {
+                let track = self.playlist.skip_back().map(Path::to_path_buf);
+                self.change_track(track);
+            }
+            Command::JumpToTrack(i) => {
+                let track = self.playlist.jump_to(i).map(Path::to_path_buf);
+                self.change_track(track);
+            }
+            Command::PlayPlaylist => self.capture = None,
+            Command::Capture(device) => self.start_capture(device.as_deref()),
+            Command::RefreshInputDevices => self.input_devices = list_input_devices(),
+            Command::TogglePause => {
+                if self.playback.is_paused() {
+                    self.playback.resume();
+                } else {
+                    self.playback.pause();
+                }
+            }
+            Command::Seek(pos) => {
+                if let Err(err) = self.playback.seek(pos) {
+                    log::warn!("{err}");
+                }
+            }
+            Command::SeekBy(seconds) => {
+                if let Err(err) = self.playback.seek_by(seconds) {
+                    log::warn!("{err}");
+                }
+            }
+            Command::SetVolume(volume) => self.playback.set_volume(volume.max(0.)),
+            Command::ChangeVolume(delta) => {
+                self.playback.set_volume((self.playback.volume() + delta).clamp(0., 2.));
+            }
+            Command::NextMode => self.painter.lock().unwrap().step_mode(1),
+            Command::PreviousMode => self.painter.lock().unwrap().step_mode(-1),
+            Command::ToggleModeLock => self.painter.lock().unwrap().toggle_mode_lock(),
+            Command::ToggleGui => self.show_gui = !self.show_gui,
+            Command::ToggleFullscreen => {
+                let fullscreen = self.window.fullscreen().is_none();
+                self.window.set_fullscreen(fullscreen.then_some(Fullscreen::Borderless(None)));
+            }
+            Command::ExitFullscreen => self.window.set_fullscreen(None),
+            Command::Screenshot { crt } => {
+                if let Err(err) = self.screenshot(crt) {
+                    log::error!("Failed to save screenshot: {err}");
+                }
+            }
+            Command::SetResolution(shape) => {
+                self.paint_shape = shape;
+                self.update_paint_shape();
+            }
+            Command::SetMatchWindow(match_window) => {
+                self.match_window = match_window;
+                self.update_paint_shape();
+            }
+            Command::LoadProfile(name) => {
+                if let Err(err) = self.load_profile(name) {
+                    log::error!("Failed to load profile: {err}");
+                }
+            }
+            Command::SaveProfileAs(name) => {
+                let painter = self.painter.lock().unwrap();
+                if let Err(err) = self.profile.save_as(&name, painter.settings()) {
+                    log::error!("Failed to save profile: {err}");
+                }
+                drop(painter);
+                self.profiles = list_profiles();
+            }
+            Command::AddBookmark(name) => {
+                if self.painter.lock().unwrap().add_bookmark(name) {
+                    self.save_bookmarks();
+                }
+            }
+            Command::RecallBookmark(i) => {
+                if let Err(err) = self.painter.lock().unwrap().recall_bookmark(i) {
+                    log::error!("Failed to recall bookmark: {err}");
+                }
+            }
+            Command::RemoveBookmark(i) => {
+                self.painter.lock().unwrap().remove_bookmark(i);
+                self.save_bookmarks();
+            }
+        }
+    }
+
+    /// Writes all bookmarks to the favorites file
+    fn save_bookmarks(&self) {
+        let Some(path) = &self.bookmarks_path else {
+            return;
+        };
+        let painter = self.painter.lock().unwrap();
+        match save_bookmarks(path, painter.bookmarks()) {
+            Ok(()) => log::info!("Saved {} bookmarks to {path:?}", painter.bookmarks().len()),
+            Err(err) => log::error!("{err}"),
+        }
+    }
+
+    /// Saves pending changes of the settings
+    pub fn save_profile(&mut self) {
+        self.profile.flush(self.painter.lock().unwrap().settings());
+    }
+
+    /// Saves the current profile and applies the settings of another one
+    fn load_profile(&mut self, name: String) -> Result<()> {
+        let profile = Profile::load(&profile_path(&name)?)?;
+        self.save_profile();
+        let mut painter = self.painter.lock().unwrap();
+        profile.apply(&mut painter.settings);
+        self.profile = ActiveProfile::new(name, profile, painter.settings());
+        Ok(())
+    }
+
+    /// Plays a track selected by the user, switching back from capture to the playlist
+    fn change_track(&mut self, track: Option<PathBuf>) {
+        self.capture = None;
+        match track {
+            Some(track) => {
+                self.play(&track);
+            }
+            None => self.playback.stop(),
+        }
+    }
+
+    /// Saves the current image as PNG in the working directory. With `crt` the last frame after
+    /// the CRT effect is saved too, with `-crt` appended to the name. Mode, waveform, track and
+    /// playback position are embedded as text.
+    fn screenshot(&self, crt: bool) -> Result<()> {
+        let (img, mode, waveform) = {
+            let painter = self.painter.lock().unwrap();
+            (painter.image().clone(), painter.mode(), painter.waveform())
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let (year, month, day, hour, minute, second) = utc_date_time(now);
+        let stamp = format!(
+            "{year:04}{month:02}{day:02}-{hour:02}{minute:02}{second:02}-{:03}",
+            now.subsec_millis()
+        );
+
+        let mut text = vec![
+            ("Software", format!("DEISS {}", env!("CARGO_PKG_VERSION"))),
+            (
+                "Creation Time",
+                format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z"),
+            ),
+        ];
+        if let Some(mode) = mode {
+            text.push(("Mode", mode.0.to_string()));
+        }
+        if let Some(waveform) = waveform {
+            text.push(("Waveform", waveform.0.to_string()));
+        }
+        match &self.capture {
+            Some(capture) => text.push(("Track", format!("Input: {}", capture.device()))),
+            None => {
+                if let Some(track) = self.playlist.current() {
+                    let name = track.file_name().unwrap_or(track.as_os_str());
+                    text.push(("Track", name.to_string_lossy().into_owned()));
+                    let position = self.playback.position().as_secs_f32();
+                    text.push(("Position", format!("{position:.3} s")));
+                }
+            }
+        }
+
+        let path = PathBuf::from(format!("deiss-{stamp}.png"));
+        write_png_with_text(&path, &img, &text)?;
+        log::info!("Saved screenshot {path:?}");
+
+        if crt {
+            let frame = self.renderer.read_crt_output(&self.gpu)?;
+            let path = PathBuf::from(format!("deiss-{stamp}-crt.png"));
+            write_png_with_text(&path, &frame, &text)?;
+            log::info!("Saved screenshot {path:?}");
+        }
+        Ok(())
+    }
+
+    /// Keeps playback in sync with the playlist.
+    ///
+    /// Tracks which finished playing advance the playlist. The track following the current one is
+    /// queued in advance so that it starts without a gap.
+    fn update_playlist(&mut self) {
+        if self.capture.is_some() {
+            return;
+        }
+
+        for _ in 0..self.playback.sync_queue() {
+            self.playlist.advance();
+        }
+
+        let Some(current) = self.playlist.current().map(Path::to_path_buf) else {
+            return;
+        };
+
+        // Start over if playback doesn't match the playlist, e.g. because the queued track was
+        // outdated by changing shuffle or repeat mode
+        if self.playback.current() != Some(current.as_path()) && !self.play(&current) {
+            self.playlist.skip_forward();
+            return;
+        }
+
+        if self.playback.queue_len() == 1
+            && let Some(next) = self.playlist.peek_next().map(Path::to_path_buf)
+            && let Err(err) = self.playback.append(&next)
+        {
+            log::error!("Failed to queue {next:?}: {err}");
+        }
+    }
+
+    /// Stops playback and records from an input device instead
+    fn start_capture(&mut self, device: Option<&str>) {
+        // drop the old stream first as some devices can't be opened twice
+        self.capture = None;
+        self.playback.stop();
+        match Capture::start(device, self.painter.clone()) {
+            Ok(capture) => self.capture = Some(capture),
+            Err(err) => log::error!("Failed to start capture: {err}"),
+        }
+    }
+
+    /// Plays a track immediately. Returns false if the track can't be played.
+    fn play(&mut self, track: &Path) -> bool {
+        log::info!("Now playing: {track:?}");
+        match self.playback.play(track) {
+            Ok(()) => true,
+            Err(err) => {
+                log::error!("Failed to play {track:?}: {err}");
+                false
+            }
+        }
+    }
+
+    pub fn handle_input(&mut self, event: &WindowEvent) -> egui_winit::EventResponse {
+        self.renderer.handle_input(&self.window, event)
+    }
+}
+
+/// UTC date and time of a time since the Unix epoch as (year, month, day, hour, minute, second)
+fn utc_date_time(t: Duration) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = t.as_secs();
+    let (days, time) = ((secs / 86_400) as i64, (secs % 86_400) as u32);
+
+    // civil date from days since 1970-01-01, see https://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+fn list_input_devices() -> Vec<String> {
+    input_devices().unwrap_or_else(|err| {
+        log::error!("{err}");
+        Vec::new()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date_time(secs: u64) -> (i64, u32, u32, u32, u32, u32) {
+        utc_date_time(Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn utc_date_time_of_known_dates() {
+        assert_eq!(date_time(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(date_time(946_684_799), (1999, 12, 31, 23, 59, 59));
+        assert_eq!(date_time(1_792_307_202), (2026, 10, 18, 7, 6, 42));
+        assert_eq!(utc_date_time(Duration::from_millis(1_792_307_202_999)).5, 42);
     }
 
-    pub fn handle_input(&mut self, event: &WindowEvent) {
-        let _ = self.renderer.handle_input(&self.window, event);
+    #[test]
+    fn utc_date_time_of_leap_days() {
+        assert_eq!(date_time(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(date_time(1_709_251_199), (2024, 2, 29, 23, 59, 59));
+        // 2100 is not a leap year
+        assert_eq!(date_time(4_107_587_696), (2100, 3, 1, 12, 34, 56));
+        assert_eq!(date_time(4_107_587_696 - 86_400), (2100, 2, 28, 12, 34, 56));
     }
 }
@@ -1,4 +1,4 @@
-use crate::{fx::Effect, painter::*, utils::*};
+use crate::{fx::*, painter::*, utils::*};
 use core::f32;
 
 pub struct DiminishCenter {
@@ -17,20 +17,21 @@ impl Effect for DiminishCenter {
     fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
         let cj = self.center.x as u32;
         let ci = self.center.y as u32;
+        let size = dot_size(img.shape());
 
         if self.center_mode {
             // center cross
-            img[(ci, ci)].scale_f(self.center_dwindle);
-            img[(ci, ci - 1)].scale_f(self.center_dwindle);
-            img[(ci, ci + 1)].scale_f(self.center_dwindle);
-            img[(ci - 1, ci)].scale_f(self.center_dwindle);
-            img[(ci + 1, ci)].scale_f(self.center_dwindle);
+            for coo in
+                [(ci, cj), (ci, cj - size), (ci, cj + size), (ci - size, cj), (ci + size, cj)]
+            {
+                for_each_in_square(img, coo, size, self.y_roi, |c| c.scale_f(self.center_dwindle));
+            }
         } else {
             // vertial line
             for i in self.y_roi.min..self.y_roi.max {
-                img[(i, cj)].scale_f(self.center_dwindle);
-                img[(i, cj - 1)].scale_f(self.center_dwindle);
-                img[(i, cj + 1)].scale_f(self.center_dwindle);
+                for j in cj - size..cj + 2 * size {
+                    img[(i, j)].scale_f(self.center_dwindle);
+                }
             }
         }
     }
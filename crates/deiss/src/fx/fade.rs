@@ -0,0 +1,24 @@
+use crate::{fx::Effect, painter::*, utils::*};
+
+/// Darkens the whole image so that the feedback decays, e.g. while no sound is playing
+pub struct Fade {
+    pub y_roi: YRoi,
+    pub factor: f32,
+}
+
+impl Fade {
+    pub fn new(factor: f32, s: &Settings) -> Self {
+        Self { y_roi: s.y_roi, factor }
+    }
+}
+
+impl Effect for Fade {
+    fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
+        for i in self.y_roi.min..self.y_roi.max {
+            for j in 0..img.cols() {
+                let col = img[(i, j)].scaled(self.factor);
+                img[(i, j)] = Rgba([col[0], col[1], col[2], 255]);
+            }
+        }
+    }
+}
@@ -1,23 +1,47 @@
 mod diminish_center;
+mod fade;
 mod grid;
 mod nuclide;
 mod one_dotty_chaser;
 mod shade_bobs;
 mod snack_bar;
 mod solar_particles;
+mod spectral;
 mod two_chasers;
 
 pub use diminish_center::*;
+pub use fade::*;
 pub use grid::*;
 pub use nuclide::*;
 pub use one_dotty_chaser::*;
 pub use shade_bobs::*;
 pub use snack_bar::*;
 pub use solar_particles::*;
+pub use spectral::*;
 pub use two_chasers::*;
 
-use crate::utils::*;
+use crate::{painter::YRoi, utils::*};
 
 pub trait Effect {
     fn render(&self, img: &mut RgbaImage, rand: &mut Minstd);
 }
+
+/// Calls `f` for every pixel of the square of `size` pixels with its top left corner at `(y, x)`
+/// which lies inside the image and `y_roi`. Used to draw dots which are one pixel wide at the
+/// reference resolution, see [crate::painter::Settings::dot_size].
+pub fn for_each_in_square(
+    img: &mut RgbaImage,
+    (y, x): (u32, u32),
+    size: u32,
+    y_roi: YRoi,
+    mut f: impl FnMut(&mut Rgba),
+) {
+    for yy in y..y.saturating_add(size) {
+        if !y_roi.contains(yy) {
+            continue;
+        }
+        for xx in x..x.saturating_add(size).min(img.cols()) {
+            f(&mut img[(yy, xx)]);
+        }
+    }
+}
@@ -38,7 +38,7 @@ impl Nuclide {
 
         let r = (3. + 40. * (g.vol.current() / g.avg_vol_narrow - 1.1)).clamp(1., 10.);
 
-        let rad = (34 + g.rand.next_idx(8)) as f32 * ((s.fxw as f32) / 1024.).max(1.);
+        let rad = (34 + g.rand.next_idx(8)) as f32;
 
         let t = g.frame as f32 + g.chaser_offset;
         let f = 7. * (t * 0.007 + 29.).sin() + 5. * (t * 0.0057 + 27.).cos();
@@ -51,16 +51,19 @@ impl Nuclide {
 
 impl Effect for Nuclide {
     fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
-        const RAD: i32 = 10;
+        // radii are in reference pixels, the brightness falls off over the same relative distance
+        let k = res_scale(img.shape().size());
+        let r = self.r * k;
+        let extent = r.ceil() as i32;
 
         for n in 0..self.nodes {
             let (th_cos, th_sin) =
                 ((n as f32) / (self.nodes as f32) * f32::consts::TAU + self.phase).sin_cos();
-            let p = self.center + (Vec2f::new(th_cos, th_sin) * self.rad).cast();
+            let p = self.center + (Vec2f::new(th_cos, th_sin) * (self.rad * k)).cast();
 
-            for y in -RAD..RAD {
-                for x in -RAD..RAD {
-                    let val = (self.r - ((x * x + y * y) as f32).sqrt()) * 25.;
+            for y in -extent..extent {
+                for x in -extent..extent {
+                    let val = (r - ((x * x + y * y) as f32).sqrt()) * 25. / k;
                     if val > 0. {
                         let coo = ((p.y + y) as u32, (p.x + x) as u32);
                         img[coo].sat_add_f_f3(val, self.col);
@@ -1,4 +1,4 @@
-use crate::{fx::Effect, painter::*, utils::*};
+use crate::{fx::*, painter::*, utils::*};
 use core::f32;
 use std::sync::{Arc, Mutex};
 
@@ -22,7 +22,8 @@ impl OneDottyChaser {
 
 impl Effect for OneDottyChaser {
     fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
-        let s = img.cols() as f32 / 640.;
+        let s = res_scale(img.shape().size());
+        let size = dot_size(img.shape());
         let t = self.time;
 
         let delta = Vec2f::new(
@@ -44,14 +45,8 @@ impl Effect for OneDottyChaser {
             });
 
             for ch in &mut self.chasers.lock().unwrap().items {
-                img[ch.coo] = ch.color;
-
-                // TODO FXW >= 880
-                img[(ch.coo.0, ch.coo.1 + 1)] = ch.color;
-                img[(ch.coo.0 + 1, ch.coo.1)] = ch.color;
-                img[(ch.coo.0 + 1, ch.coo.1 + 1)] = ch.color;
-
-                ch.coo.1 += 1;
+                for_each_in_square(img, ch.coo, 2 * size, self.y_roi, |c| *c = ch.color);
+                ch.coo.1 += size;
             }
         }
     }
@@ -1,4 +1,4 @@
-use crate::{fx::Effect, utils::*};
+use crate::{fx::*, painter::*, utils::*};
 use core::f32;
 
 pub struct ShadeBobs {
@@ -34,9 +34,10 @@ impl ShadeBobs {
 
 impl Effect for ShadeBobs {
     fn render(&self, img: &mut RgbaImage, rand: &mut Minstd) {
-        let shape = img.shape();
-        let cols = shape.cols() as usize;
-        let pxls = img.as_slice_mut();
+        let k = res_scale(img.shape().size());
+        let size = dot_size(img.shape());
+        let step = size as i32;
+        let roi = YRoi { min: 0, max: img.rows() - 1 };
 
         for x in 0..self.count {
             let col: [u32; 3] = core::array::from_fn(|c| {
@@ -44,20 +45,18 @@ impl Effect for ShadeBobs {
             });
 
             let mut a = self.center.x
-                + (self.micro_rad[x][0] * (self.floatframe * self.micro_f[x][0]).cos()
+                + ((self.micro_rad[x][0] * (self.floatframe * self.micro_f[x][0]).cos()
                     + self.micro_rad[x][2] * (self.floatframe * self.micro_f[x][1]).cos())
-                    as i32;
+                    * k) as i32;
 
             let mut b = self.center.y
-                + (self.micro_rad[x][1] * (self.floatframe * self.micro_f[x][2]).cos()
+                + ((self.micro_rad[x][1] * (self.floatframe * self.micro_f[x][2]).cos()
                     + self.micro_rad[x][3] * (self.floatframe * self.micro_f[x][3]).cos())
-                    as i32;
+                    * k) as i32;
 
             for _ in 0..4 {
-                a += rand.next_idx(5) as i32 - 2;
-                b += rand.next_idx(5) as i32 - 2;
-
-                let idx = shape.offset((b as u32, a as u32));
+                a += (rand.next_idx(5) as i32 - 2) * step;
+                b += (rand.next_idx(5) as i32 - 2) * step;
 
                 let mut delta = [[0; 3]; 2];
 
@@ -68,11 +67,16 @@ impl Effect for ShadeBobs {
                     }
                 }
 
-                pxls[idx].sat_add_u3(delta[0]);
-                pxls[idx + 1].sat_add_u3(delta[1]);
-                pxls[idx - 1].sat_add_u3(delta[1]);
-                pxls[idx + cols].sat_add_u3(delta[1]);
-                pxls[idx - cols].sat_add_u3(delta[1]);
+                for (dy, dx, delta) in [
+                    (0, 0, delta[0]),
+                    (0, 1, delta[1]),
+                    (0, -1, delta[1]),
+                    (1, 0, delta[1]),
+                    (-1, 0, delta[1]),
+                ] {
+                    let coo = ((b + dy * step) as u32, (a + dx * step) as u32);
+                    for_each_in_square(img, coo, size, roi, |c| c.sat_add_u3(delta));
+                }
             }
         }
     }
@@ -1,4 +1,4 @@
-use crate::{fx::Effect, painter::*, utils::*};
+use crate::{fx::*, painter::*, utils::*};
 use core::f32;
 
 pub struct SnackBar {
@@ -28,7 +28,8 @@ impl SnackBar {
 
 impl Effect for SnackBar {
     fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
-        let s = img.cols() as f32 / 640.;
+        let s = res_scale(img.shape().size());
+        let size = dot_size(img.shape());
 
         for ch in 0..3 {
             let t = self.frame[ch] * 0.55 / (0.08 * 20.);
@@ -43,7 +44,7 @@ impl Effect for SnackBar {
                 13. * (t * 0.1304 + 12.).cos() + 11. * (t * 0.1103 + 21.).cos(),
             );
 
-            let n = (s * 50.) as usize;
+            let n = 50;
             let n_inv = 1.0 / (n as f32);
 
             for k in 0..n {
@@ -51,12 +52,12 @@ impl Effect for SnackBar {
                 let d = d1 * q + d2 * (1.0 - q);
                 let p = self.center + (d * s).cast();
                 let coo = (p.y as u32, p.x as u32);
-                if self.y_roi.contains(coo.0) {
-                    let v = &mut img[coo][ch];
+                for_each_in_square(img, coo, size, self.y_roi, |col| {
+                    let v = &mut col[ch];
                     if *v < 223 {
                         *v += 16;
                     }
-                }
+                });
             }
         }
     }
@@ -1,4 +1,4 @@
-use crate::{fx::Effect, painter::Globals, utils::*};
+use crate::{fx::*, painter::*, utils::*};
 use core::f32;
 
 pub struct SolarParticles {
@@ -23,18 +23,19 @@ impl SolarParticles {
 
 impl Effect for SolarParticles {
     fn render(&self, img: &mut RgbaImage, rand: &mut Minstd) {
-        let shape = img.shape();
-        let cols = shape.cols() as usize;
-        let pxls = img.as_slice_mut();
+        // the disk has a radius of 35 reference pixels
+        let k = res_scale(img.shape().size());
+        let size = dot_size(img.shape());
+        let step = size as i32;
+        let roi = YRoi { min: 0, max: img.rows() - 1 };
+        let disk_rad = ((35. * k).round() as u32).max(1);
 
         for _ in 0..self.count {
-            let (delta, r) = sample_disk(35, rand);
+            let (delta, r) = sample_disk(disk_rad, rand);
+            let r = r * 35 / disk_rad;
             let p = self.center + delta;
-            let coo = (p.y as u32, p.x as u32);
 
-            let idx = shape.offset(coo);
-
-            let col = pxls[idx];
+            let col = img[(p.y as u32, p.x as u32)];
 
             let mut delta = [[0; 3]; 3];
 
@@ -49,15 +50,20 @@ impl Effect for SolarParticles {
                 }
             }
 
-            pxls[idx].sat_add_u3(delta[0]);
-            pxls[idx + 1].sat_add_u3(delta[1]);
-            pxls[idx - 1].sat_add_u3(delta[1]);
-            pxls[idx + cols].sat_add_u3(delta[1]);
-            pxls[idx - cols].sat_add_u3(delta[1]);
-            pxls[idx + cols + 1].sat_add_u3(delta[2]);
-            pxls[idx + cols - 1].sat_add_u3(delta[2]);
-            pxls[idx - cols + 1].sat_add_u3(delta[2]);
-            pxls[idx - cols - 1].sat_add_u3(delta[2]);
+            for (dy, dx, delta) in [
+                (0, 0, delta[0]),
+                (0, 1, delta[1]),
+                (0, -1, delta[1]),
+                (1, 0, delta[1]),
+                (-1, 0, delta[1]),
+                (1, 1, delta[2]),
+                (1, -1, delta[2]),
+                (-1, 1, delta[2]),
+                (-1, -1, delta[2]),
+            ] {
+                let coo = ((p.y + dy * step) as u32, (p.x + dx * step) as u32);
+                for_each_in_square(img, coo, size, roi, |c| c.sat_add_u3(delta));
+            }
         }
     }
 }
@@ -0,0 +1,65 @@
+use crate::{fx::*, painter::*, utils::*};
+use core::f32;
+
+/// Radial spectrum around the center: one ray per band whose length follows the band level.
+/// Rays are mirrored so that the pattern is symmetric and slowly rotate over time.
+pub struct Spectral {
+    pub y_roi: YRoi,
+    pub center: Vec2i,
+    pub levels: Vec<f32>,
+    pub rotation: f32,
+}
+
+impl Spectral {
+    pub fn new(center: Vec2i, s: &Settings, g: &Globals) -> Self {
+        Self {
+            y_roi: s.y_roi,
+            center,
+            levels: g.spectrum.normalized().to_vec(),
+            rotation: g.floatframe * 0.01,
+        }
+    }
+}
+
+impl Effect for Spectral {
+    fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
+        let n = self.levels.len();
+        if n == 0 {
+            return;
+        }
+
+        let s = res_scale(img.shape().size());
+        let size = dot_size(img.shape());
+        let r_min = 12. * s;
+        let r_span = 90. * s;
+
+        for (k, &v) in self.levels.iter().enumerate() {
+            if v < 0.05 {
+                continue;
+            }
+
+            // bass is red, treble is blue
+            let q = k as f32 / n as f32;
+            let intensity = 48. * v;
+            let col = [intensity * (1. - q), intensity * (1. - (2. * q - 1.).abs()), intensity * q]
+                .map(|c| c as u8);
+
+            let steps = (v * r_span) as u32;
+            for side in [-1., 1.] {
+                let theta = self.rotation + side * (k as f32 + 0.5) / n as f32 * f32::consts::PI;
+                let dir = Vec2f::new(theta.cos(), theta.sin());
+
+                for i in (0..steps).step_by(size as usize) {
+                    let p = self.center.cast::<f32>() + dir * (r_min + i as f32);
+                    let (x, y) = (p.x as i32, p.y as i32);
+                    if x < 0 || x >= img.cols() as i32 || y < 0 || !self.y_roi.contains(y as u32) {
+                        break;
+                    }
+                    for_each_in_square(img, (y as u32, x as u32), size, self.y_roi, |c| {
+                        c.sat_add_u3(col)
+                    });
+                }
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{fx::Effect, painter::*, utils::*};
+use crate::{fx::*, painter::*, utils::*};
 use core::f32;
 
 pub struct TwoChasers {
@@ -7,6 +7,9 @@ pub struct TwoChasers {
     pub passes: usize,
     pub frame: f32,
     pub time_scale: f32,
+
+    /// Beat pulse of the tempo tracker which makes the chasers jump outwards and flash
+    pub pulse: f32,
 }
 
 impl TwoChasers {
@@ -17,14 +20,18 @@ impl TwoChasers {
             passes,
             frame: (g.floatframe + g.chaser_offset) * g.time_scale,
             time_scale: g.time_scale,
+            pulse: g.tempo.pulse(),
         }
     }
 }
 
 impl Effect for TwoChasers {
     fn render(&self, img: &mut RgbaImage, _: &mut Minstd) {
-        let s = img.cols() as f32 / 640.;
-        let n = (20. * s) as usize;
+        let s = res_scale(img.shape().size());
+        let size = dot_size(img.shape());
+        let n = 20;
+        let radius = s * (1. + 0.15 * self.pulse);
+        let keep = 0.6 - 0.3 * self.pulse;
 
         let mut t = self.frame;
         for _ in 0..n {
@@ -42,20 +49,18 @@ impl Effect for TwoChasers {
                         52. * (t * 0.1304 + 12.).cos() + 51. * (t * 0.1103 + 21.).cos(),
                     )
                 };
-                let p = self.center + (delta * s).cast();
+                let p = self.center + (delta * radius).cast();
 
                 let coo = (p.y as u32, p.x as u32);
 
-                if self.y_roi.contains(coo.0) {
-                    let col = &mut img[coo];
-
+                for_each_in_square(img, coo, size, self.y_roi, |col| {
                     *col = Rgba([
-                        255 - ((255 - col[0]) as f32 * 0.6) as u8,
-                        255 - ((255 - col[1]) as f32 * 0.6) as u8,
-                        255 - ((255 - col[2]) as f32 * 0.6) as u8,
+                        255 - ((255 - col[0]) as f32 * keep) as u8,
+                        255 - ((255 - col[1]) as f32 * keep) as u8,
+                        255 - ((255 - col[2]) as f32 * keep) as u8,
                         255,
                     ]);
-                }
+                });
             }
         }
     }
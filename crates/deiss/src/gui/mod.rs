@@ -1,14 +1,111 @@
-use crate::{painter::*, renderer::CrtShaderSettings, utils::*};
+use crate::{
+    audio::{Playlist, RepeatMode},
+    command::{Command, SHORTCUTS},
+    painter::*,
+    renderer::CrtShaderSettings,
+    utils::*,
+};
+use std::time::Duration;
 
-/// Default guid
-pub fn deiss_gui(ctx: &egui::Context, settings: &mut Settings, globals: &mut Globals) {
+/// State of the app shown next to the painter settings
+pub struct AppGuiState<'a> {
+    pub playlist: &'a mut Playlist,
+
+    /// Names of all audio input devices
+    pub input_devices: &'a [String],
+
+    /// Name of the device which is recorded, if capturing
+    pub capture_device: Option<&'a str>,
+
+    pub paused: bool,
+    pub volume: f32,
+
+    /// Playback position and duration of the current track
+    pub position: Duration,
+    pub duration: Option<Duration>,
+
+    /// The mode which is currently shown
+    pub mode: Option<ModeId>,
+
+    /// Internal resolution chosen by the user
+    pub paint_shape: Shape2,
+
+    /// Adapt the aspect ratio of the internal resolution to the window
+    pub match_window: bool,
+
+    /// Name of the profile which receives the changes
+    pub profile: &'a str,
+
+    /// Names of all saved profiles
+    pub profiles: &'a [String],
+
+    pub bookmarks: &'a [Bookmark],
+}
+
+/// Internal resolutions offered in the GUI as (width, height)
+const RESOLUTIONS: [(u32, u32); 9] = [
+    (640, 480),
+    (800, 600),
+    (1024, 768),
+    (1280, 960),
+    (1280, 720),
+    (1920, 1080),
+    (2560, 1080),
+    (1024, 1024),
+    (1080, 1920),
+];
+
+/// Default guid. Actions which can't be applied directly are pushed to `commands`.
+pub fn deiss_gui(
+    ctx: &egui::Context,
+    settings: &mut Settings,
+    globals: &mut Globals,
+    app: AppGuiState,
+    commands: &mut Vec<Command>,
+) {
     egui::Window::new("DEISS").resizable(true).vscroll(true).default_open(true).show(ctx, |ui| {
+        egui::CollapsingHeader::new("Transport")
+            .default_open(true)
+            .show(ui, |ui| transport_gui(ui, &app, &settings.mode_prefs, commands));
+        egui::CollapsingHeader::new("Audio Input")
+            .default_open(false)
+            .show(ui, |ui| audio_input_gui(ui, &app, commands));
+        egui::CollapsingHeader::new("Playlist")
+            .default_open(false)
+            .show(ui, |ui| playlist_gui(ui, app.playlist, commands));
+        egui::CollapsingHeader::new("Resolution")
+            .default_open(false)
+            .show(ui, |ui| resolution_gui(ui, settings.shape(), &app, commands));
+        egui::CollapsingHeader::new("Profile")
+            .default_open(false)
+            .show(ui, |ui| profile_gui(ui, &app, commands));
+        egui::CollapsingHeader::new("Bookmarks")
+            .default_open(false)
+            .show(ui, |ui| bookmarks_gui(ui, &app, &mut settings.bookmark_share, commands));
         egui::CollapsingHeader::new("Mode Selection")
             .default_open(false)
             .show(ui, |ui| mode_prefs_gui(ui, &mut settings.mode_prefs));
         egui::CollapsingHeader::new("Waveform Selection")
             .default_open(false)
             .show(ui, |ui| waveform_prefs_gui(ui, &mut settings.waveform_prefs));
+        egui::CollapsingHeader::new("Mode Switching")
+            .default_open(false)
+            .show(ui, |ui| switch_gui(ui, &mut settings.switch));
+        egui::CollapsingHeader::new("Transition")
+            .default_open(false)
+            .show(ui, |ui| transition_gui(ui, &mut settings.transition));
+        egui::CollapsingHeader::new("Dynamic Warp")
+            .default_open(false)
+            .show(ui, |ui| dynamic_warp_gui(ui, &mut settings.dynamic));
+        egui::CollapsingHeader::new("Spectrum")
+            .default_open(false)
+            .show(ui, |ui| spectrum_gui(ui, &mut settings.spectrum, &globals.spectrum));
+        egui::CollapsingHeader::new("Levels")
+            .default_open(false)
+            .show(ui, |ui| levels_gui(ui, globals));
+        egui::CollapsingHeader::new("Tempo")
+            .default_open(false)
+            .show(ui, |ui| tempo_gui(ui, &globals.tempo));
         egui::CollapsingHeader::new("CRT Shader")
             .default_open(true)
             .show(ui, |ui| crt_shader_gui(ui, &mut settings.crt_shader_settings));
@@ -20,6 +117,291 @@ pub fn deiss_gui(ctx: &egui::Context, settings: &mut Settings, globals: &mut Glo
     });
 }
 
+/// GUI for playback, mode and window controls
+fn transport_gui(
+    ui: &mut egui::Ui,
+    app: &AppGuiState,
+    mode_prefs: &ModePrefs,
+    commands: &mut Vec<Command>,
+) {
+    ui.add_enabled_ui(app.capture_device.is_none(), |ui| {
+        ui.horizontal(|ui| {
+            if ui.button("⏮").on_hover_text("Previous track").clicked() {
+                commands.push(Command::SkipBack);
+            }
+            if ui.button("⏪").on_hover_text("Seek -10 s").clicked() {
+                commands.push(Command::SeekBy(-10.));
+            }
+            let play_pause = if app.paused { "▶" } else { "⏸" };
+            if ui.button(play_pause).on_hover_text("Pause / resume").clicked() {
+                commands.push(Command::TogglePause);
+            }
+            if ui.button("⏩").on_hover_text("Seek +10 s").clicked() {
+                commands.push(Command::SeekBy(10.));
+            }
+            if ui.button("⏭").on_hover_text("Next track").clicked() {
+                commands.push(Command::SkipForward);
+            }
+        });
+
+        seek_bar_gui(ui, app.position, app.duration, commands);
+
+        let mut volume = app.volume;
+        if ui.add(egui::Slider::new(&mut volume, 0.0..=2.0).text("Volume")).changed() {
+            commands.push(Command::SetVolume(volume));
+        }
+    });
+
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        if ui.button("◀").on_hover_text("Previous mode").clicked() {
+            commands.push(Command::PreviousMode);
+        }
+        match app.mode {
+            Some(mode) => ui.label(format!("Mode {}", mode.0)),
+            None => ui.label("Mode -"),
+        };
+        if ui.button("▶").on_hover_text("Next mode").clicked() {
+            commands.push(Command::NextMode);
+        }
+        let mut locked = mode_prefs.priority().is_some();
+        if ui.checkbox(&mut locked, "Lock").changed() {
+            commands.push(Command::ToggleModeLock);
+        }
+    });
+
+    ui.horizontal(|ui| {
+        if ui.button("Fullscreen").clicked() {
+            commands.push(Command::ToggleFullscreen);
+        }
+        if ui.button("Screenshot").on_hover_text("Save the painted image").clicked() {
+            commands.push(Command::Screenshot { crt: false });
+        }
+        if ui
+            .button("Screenshot + CRT")
+            .on_hover_text("Also save the frame after the CRT effect")
+            .clicked()
+        {
+            commands.push(Command::Screenshot { crt: true });
+        }
+        if ui.button("Hide GUI").clicked() {
+            commands.push(Command::ToggleGui);
+        }
+    });
+
+    egui::CollapsingHeader::new("Keyboard Shortcuts").default_open(false).show(ui, |ui| {
+        egui::Grid::new("shortcuts").show(ui, |ui| {
+            for (key, description) in SHORTCUTS {
+                ui.label(key);
+                ui.label(description);
+                ui.end_row();
+            }
+        });
+    });
+}
+
+/// Shows the playback position and allows to jump to another position
+fn seek_bar_gui(
+    ui: &mut egui::Ui,
+    position: Duration,
+    duration: Option<Duration>,
+    commands: &mut Vec<Command>,
+) {
+    let Some(duration) = duration else {
+        ui.label(format_time(position));
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        let mut secs = position.as_secs_f32();
+        let slider = egui::Slider::new(&mut secs, 0.0..=duration.as_secs_f32()).show_value(false);
+        let response = ui.add(slider);
+
+        // seek once the handle is released instead of on every step of a drag
+        if response.drag_stopped() || (response.changed() && !response.dragged()) {
+            commands.push(Command::Seek(Duration::from_secs_f32(secs)));
+        }
+
+        ui.label(format!("{} / {}", format_time(position), format_time(duration)));
+    });
+}
+
+/// Formats a duration as minutes and seconds
+fn format_time(t: Duration) -> String {
+    let secs = t.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// GUI to switch between the playlist and live capture from an input device
+fn audio_input_gui(ui: &mut egui::Ui, app: &AppGuiState, commands: &mut Vec<Command>) {
+    let capturing = app.capture_device.is_some();
+    if ui
+        .add_enabled(
+            !app.playlist.tracks().is_empty(),
+            egui::Button::selectable(!capturing, "Playlist"),
+        )
+        .clicked()
+        && capturing
+    {
+        commands.push(Command::PlayPlaylist);
+    }
+
+    ui.separator();
+
+    for device in app.input_devices {
+        let is_current = app.capture_device == Some(device.as_str());
+        if ui.selectable_label(is_current, device).clicked() && !is_current {
+            commands.push(Command::Capture(Some(device.clone())));
+        }
+    }
+
+    ui.separator();
+
+    if ui.button("Refresh Devices").clicked() {
+        commands.push(Command::RefreshInputDevices);
+    }
+}
+
+/// GUI to choose the internal resolution
+fn resolution_gui(
+    ui: &mut egui::Ui,
+    painted: Shape2,
+    app: &AppGuiState,
+    commands: &mut Vec<Command>,
+) {
+    let name = |shape: Shape2| format!("{}x{}", shape.cols(), shape.rows());
+    egui::ComboBox::from_label("Resolution").selected_text(name(app.paint_shape)).show_ui(
+        ui,
+        |ui| {
+            for (w, h) in RESOLUTIONS {
+                let shape = (h, w).into();
+                if ui.selectable_label(shape == app.paint_shape, name(shape)).clicked()
+                    && shape != app.paint_shape
+                {
+                    commands.push(Command::SetResolution(shape));
+                }
+            }
+        },
+    );
+
+    let mut match_window = app.match_window;
+    if ui
+        .checkbox(&mut match_window, "Match Window Aspect")
+        .on_hover_text("Keep the number of pixels and follow the aspect ratio of the window")
+        .changed()
+    {
+        commands.push(Command::SetMatchWindow(match_window));
+    }
+
+    ui.label(format!("Painting {}", name(painted)));
+}
+
+/// GUI to switch between saved profiles and save the settings as a new one
+fn profile_gui(ui: &mut egui::Ui, app: &AppGuiState, commands: &mut Vec<Command>) {
+    egui::ComboBox::from_label("Profile").selected_text(app.profile).show_ui(ui, |ui| {
+        for name in app.profiles {
+            if ui.selectable_label(name == app.profile, name).clicked() && name != app.profile {
+                commands.push(Command::LoadProfile(name.clone()));
+            }
+        }
+    });
+
+    if let Some(name) = name_input(ui, "new_profile", "Save As", false) {
+        commands.push(Command::SaveProfileAs(name));
+    }
+
+    ui.label("Changes are saved automatically");
+}
+
+/// GUI to bookmark the look which is shown and to show bookmarks again
+fn bookmarks_gui(
+    ui: &mut egui::Ui,
+    app: &AppGuiState,
+    bookmark_share: &mut f32,
+    commands: &mut Vec<Command>,
+) {
+    ui.add_enabled_ui(app.mode.is_some(), |ui| {
+        if let Some(name) = name_input(ui, "new_bookmark", "Bookmark", true) {
+            commands.push(Command::AddBookmark((!name.is_empty()).then_some(name)));
+        }
+    });
+
+    ui.add(egui::Slider::new(bookmark_share, 0.0..=1.0).text("Share in Rotation"))
+        .on_hover_text("Probability that a mode switch shows a bookmark");
+
+    ui.separator();
+
+    for (i, bookmark) in app.bookmarks.iter().enumerate() {
+        ui.horizontal(|ui| {
+            if ui.small_button("✕").on_hover_text("Delete").clicked() {
+                commands.push(Command::RemoveBookmark(i));
+            }
+            let text = format!("{} (mode {})", bookmark.name, bookmark.mode.0);
+            if ui.selectable_label(false, text).on_hover_text("Show").clicked() {
+                commands.push(Command::RecallBookmark(i));
+            }
+        });
+    }
+}
+
+/// Text field for a name with a button next to it. Returns the name when the button is clicked.
+/// The name being typed is kept in egui's memory between frames.
+fn name_input(ui: &mut egui::Ui, id_salt: &str, button: &str, allow_empty: bool) -> Option<String> {
+    ui.horizontal(|ui| {
+        let id = ui.id().with(id_salt);
+        let mut name = ui.data_mut(|d| d.get_temp::<String>(id)).unwrap_or_default();
+        ui.add(egui::TextEdit::singleline(&mut name).hint_text("Name").desired_width(120.));
+        let enabled = allow_empty || !name.is_empty();
+        let clicked = ui.add_enabled(enabled, egui::Button::new(button)).clicked();
+        let result = clicked.then(|| std::mem::take(&mut name));
+        ui.data_mut(|d| d.insert_temp(id, name));
+        result
+    })
+    .inner
+}
+
+/// GUI to show the playlist and change tracks
+fn playlist_gui(ui: &mut egui::Ui, playlist: &mut Playlist, commands: &mut Vec<Command>) {
+    ui.horizontal(|ui| {
+        if ui.button("⏮ Previous").clicked() {
+            commands.push(Command::SkipBack);
+        }
+        if ui.button("Next ⏭").clicked() {
+            commands.push(Command::SkipForward);
+        }
+
+        let mut shuffle = playlist.shuffle();
+        if ui.checkbox(&mut shuffle, "Shuffle").changed() {
+            playlist.set_shuffle(shuffle);
+        }
+
+        let mut repeat = playlist.repeat();
+        egui::ComboBox::from_label("Repeat").selected_text(format!("{repeat:?}")).show_ui(
+            ui,
+            |ui| {
+                ui.selectable_value(&mut repeat, RepeatMode::Off, "Off");
+                ui.selectable_value(&mut repeat, RepeatMode::All, "All");
+                ui.selectable_value(&mut repeat, RepeatMode::One, "One");
+            },
+        );
+        if repeat != playlist.repeat() {
+            playlist.set_repeat(repeat);
+        }
+    });
+
+    ui.separator();
+
+    let current = playlist.current_index();
+    for (i, track) in playlist.tracks().iter().enumerate() {
+        let name = track.file_name().unwrap_or(track.as_os_str()).to_string_lossy();
+        let text = format!("{:>3}. {name}", i + 1);
+        if ui.selectable_label(current == Some(i), text).clicked() {
+            commands.push(Command::JumpToTrack(i));
+        }
+    }
+}
+
 /// GUI to change mode selection preferences
 fn mode_prefs_gui(ui: &mut egui::Ui, mode_prefs: &mut ModePrefs) {
     let current_priority = mode_prefs.priority();
@@ -117,6 +499,103 @@ fn waveform_prefs_gui(ui: &mut egui::Ui, waveform_prefs: &mut WaveformPrefs) {
     }
 }
 
+/// GUI to choose when modes switch
+fn switch_gui(ui: &mut egui::Ui, settings: &mut SwitchSettings) {
+    egui::ComboBox::from_label("Policy").selected_text(settings.policy.name()).show_ui(ui, |ui| {
+        for policy in SwitchPolicyKind::ALL {
+            ui.selectable_value(&mut settings.policy, policy, policy.name());
+        }
+    });
+
+    let waits_for_music =
+        matches!(settings.policy, SwitchPolicyKind::Beat | SwitchPolicyKind::Section);
+    let has_max = waits_for_music || settings.policy == SwitchPolicyKind::Random;
+
+    ui.add(egui::Slider::new(&mut settings.min_interval, 0.5..=30.).text("Min Interval (s)"));
+    ui.add_enabled(
+        has_max,
+        egui::Slider::new(&mut settings.max_interval, 0.5..=60.).text("Max Interval (s)"),
+    );
+    ui.add_enabled(
+        settings.policy == SwitchPolicyKind::Beat,
+        egui::Slider::new(&mut settings.beats_per_bar, 1..=8).text("Beats per Bar"),
+    );
+    ui.add_enabled(
+        settings.policy == SwitchPolicyKind::Section,
+        egui::Slider::new(&mut settings.novelty_threshold, 0.1..=2.0).text("Novelty Threshold"),
+    );
+}
+
+/// GUI for the cross-fade between warp maps on mode switches
+fn transition_gui(ui: &mut egui::Ui, settings: &mut TransitionSettings) {
+    ui.add(egui::Slider::new(&mut settings.frames, 0..=300).text("Frames"));
+    egui::ComboBox::from_label("Curve").selected_text(settings.curve.name()).show_ui(ui, |ui| {
+        for curve in TransitionCurve::ALL {
+            ui.selectable_value(&mut settings.curve, curve, curve.name());
+        }
+    });
+}
+
+/// GUI for the audio-reactive modulation of warp maps
+fn dynamic_warp_gui(ui: &mut egui::Ui, settings: &mut DynamicWarpSettings) {
+    ui.add(egui::Slider::new(&mut settings.zoom_pulse, 0.0..=0.1).text("Kick Zoom"));
+    ui.add(egui::Slider::new(&mut settings.rebake_frames, 1..=8).text("Re-bake Every (frames)"));
+    ui.add(egui::Slider::new(&mut settings.grid_cell, 2..=32).text("Grid Cell (px)"));
+}
+
+/// Shows the spectrum analyzer output and its settings
+fn spectrum_gui(ui: &mut egui::Ui, settings: &mut SpectrumSettings, spectrum: &Spectrum) {
+    let (response, painter) =
+        ui.allocate_painter(egui::vec2(ui.available_width(), 80.), egui::Sense::hover());
+    let rect = response.rect;
+    painter.rect_filled(rect, 0., egui::Color32::from_gray(20));
+
+    let n = spectrum.normalized().len().max(1);
+    let bar_width = rect.width() / n as f32;
+    let z = if spectrum.level() > 0. { 1. / spectrum.level() } else { 0. };
+    for (i, (&v, &peak)) in spectrum.normalized().iter().zip(spectrum.peaks()).enumerate() {
+        let x = rect.left() + i as f32 * bar_width;
+        let top = rect.bottom() - v * rect.height();
+        let bar = egui::Rect::from_min_max(
+            egui::pos2(x + 1., top),
+            egui::pos2(x + bar_width - 1., rect.bottom()),
+        );
+        painter.rect_filled(bar, 0., egui::Color32::from_rgb(100, 200, 100));
+
+        let peak_y = rect.bottom() - (peak * z).min(1.) * rect.height();
+        painter.hline(
+            x + 1.0..=x + bar_width - 1.,
+            peak_y,
+            (1., egui::Color32::from_rgb(255, 200, 100)),
+        );
+    }
+
+    ui.add(egui::Slider::new(&mut settings.bands, 4..=64).text("Bands"));
+    ui.add(egui::Slider::new(&mut settings.smoothing, 0.0..=0.99).text("Smoothing"));
+    ui.add(egui::Slider::new(&mut settings.peak_decay, 0.8..=0.999).text("Peak Decay"));
+    ui.add(
+        egui::Slider::new(&mut settings.normalization_decay, 0.9..=0.9999)
+            .text("Normalization Decay"),
+    );
+}
+
+/// Shows the analyzed volume levels and whether the sound counts as empty
+fn levels_gui(ui: &mut egui::Ui, g: &Globals) {
+    ui.label(format!("Volume: {:.1} (average {:.1})", g.vol.current(), g.avg_vol));
+    ui.label(format!("Peaks: {:.1}", g.avg_vol_peaks));
+    ui.label(if g.sound_empty { "Silent" } else { "Sound" });
+}
+
+/// Shows the detected tempo and the position within the beat
+fn tempo_gui(ui: &mut egui::Ui, tempo: &Tempo) {
+    match tempo.bpm() {
+        Some(bpm) => ui.label(format!("{bpm:.1} bpm (confidence {:.2})", tempo.confidence())),
+        None => ui.label("No tempo detected"),
+    };
+    let text = if tempo.is_locked() { "Locked" } else { "Searching" };
+    ui.add(egui::ProgressBar::new(tempo.phase()).text(text));
+}
+
 fn crt_shader_gui(ui: &mut egui::Ui, settings: &mut CrtShaderSettings) {
     ui.checkbox(&mut settings.warp_enabled, "Warp Enabled");
     ui.add_enabled(
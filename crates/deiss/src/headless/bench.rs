@@ -0,0 +1,93 @@
+use crate::{config::Config, painter::*, utils::*};
+use eyre::{Result, bail};
+use std::time::{Duration, Instant};
+
+pub struct BenchConfig {
+    /// Modes to measure. All modes if empty.
+    pub modes: Vec<ModeId>,
+
+    /// Number of times `process_map` runs per mode
+    pub iterations: u32,
+}
+
+/// Timings of one mode
+pub struct BenchResult {
+    pub mode: ModeId,
+    pub bake_scalar: Duration,
+    pub bake_parallel: Duration,
+
+    /// Average time of one `process_map` call
+    pub process_scalar: Duration,
+    pub process_parallel: Duration,
+}
+
+/// Compares the parallel warp map functions with their single-threaded reference versions at the
+/// paint resolution of the config. Fails if any output differs.
+pub fn run_bench(config: &Config, bench: &BenchConfig) -> Result<Vec<BenchResult>> {
+    let mut painter = Painter::new(config.paint_shape, Clock::fixed(30.), config.seed);
+    painter.load_presets(&config.preset_dirs)?;
+    config.apply(&mut painter.settings);
+
+    let modes =
+        if bench.modes.is_empty() { painter.library.mode_ids() } else { bench.modes.clone() };
+    let s = &painter.settings;
+    let g = &mut painter.globals;
+
+    // noise gives every source pixel a different value so that any wrong weight shows
+    let mut src = RgbaImage::from_value(s.shape(), Rgba::BLACK);
+    src.apply(|_| {
+        let v = g.rand.next_idx(1 << 24);
+        Rgba([v as u8, (v >> 8) as u8, (v >> 16) as u8, 255])
+    });
+
+    let mut results = Vec::new();
+    for mode in modes {
+        if !painter.library.contains(mode) {
+            bail!("Unknown mode {}. Use `deiss list-modes` to see available modes.", mode.0);
+        }
+        let spec = WarpSpec::generate_mode(mode, s, &painter.library, g);
+        let center = spec.center.cast();
+
+        let start = Instant::now();
+        let scalar = bake_scalar(s, center, spec.weightsum, spec.damping, &spec.tf);
+        let bake_scalar = start.elapsed();
+
+        let start = Instant::now();
+        let parallel = bake(s, center, spec.weightsum, spec.damping, &spec.tf);
+        let bake_parallel = start.elapsed();
+
+        if scalar.map.as_slice() != parallel.map.as_slice() {
+            bail!("Parallel bake of mode {} differs from the scalar bake", mode.0);
+        }
+
+        let map = parallel.map.as_slice();
+        let mut expected = src.clone();
+        let mut actual = src.clone();
+
+        let start = Instant::now();
+        for _ in 0..bench.iterations {
+            process_map_scalar(s, map, &src, &mut expected);
+        }
+        let process_scalar = start.elapsed() / bench.iterations.max(1);
+
+        let start = Instant::now();
+        for _ in 0..bench.iterations {
+            process_map(s, map, &src, &mut actual);
+        }
+        let process_parallel = start.elapsed() / bench.iterations.max(1);
+
+        if expected.as_slice().iter().zip(actual.as_slice()).any(|(a, b)| a.0 != b.0) {
+            bail!("Parallel process_map of mode {} differs from the scalar version", mode.0);
+        }
+
+        results.push(BenchResult {
+            mode,
+            bake_scalar,
+            bake_parallel,
+            process_scalar,
+            process_parallel,
+        });
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,109 @@
+use crate::utils::RgbaImage;
+use eyre::{Result, eyre};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Stdout, Write},
+    path::{Path, PathBuf},
+};
+
+/// Image format used to store rendered frames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// One numbered PNG file per frame
+    Png,
+
+    /// One numbered binary PPM (P6) file per frame
+    Ppm,
+
+    /// Raw RGBA bytes of all frames concatenated on stdout
+    Raw,
+}
+
+impl FrameFormat {
+    pub fn from_name(name: &str) -> Result<Self> {
+        match name {
+            "png" => Ok(FrameFormat::Png),
+            "ppm" => Ok(FrameFormat::Ppm),
+            "raw" => Ok(FrameFormat::Raw),
+            _ => Err(eyre!("Unknown frame format '{name}' (expected png, ppm or raw)")),
+        }
+    }
+}
+
+/// Writes rendered frames to disk or stdout
+pub enum FrameSink {
+    Files { dir: PathBuf, format: FrameFormat },
+    Stdout(BufWriter<Stdout>),
+}
+
+impl FrameSink {
+    /// Creates a sink which writes frames in the given format. The directory is ignored for raw
+    /// output which is always written to stdout.
+    pub fn new<P: AsRef<Path>>(dir: P, format: FrameFormat) -> Result<Self> {
+        match format {
+            FrameFormat::Raw => Ok(FrameSink::Stdout(BufWriter::new(std::io::stdout()))),
+            FrameFormat::Png | FrameFormat::Ppm => {
+                let dir = dir.as_ref().to_path_buf();
+                fs::create_dir_all(&dir)
+                    .map_err(|e| eyre!("Failed to create output directory {dir:?}: {e}"))?;
+                Ok(FrameSink::Files { dir, format })
+            }
+        }
+    }
+
+    pub fn write_frame(&mut self, index: u64, img: &RgbaImage) -> Result<()> {
+        match self {
+            FrameSink::Files { dir, format: FrameFormat::Png } => {
+                write_png(&dir.join(format!("frame_{index:06}.png")), img)
+            }
+            FrameSink::Files { dir, format: _ } => {
+                write_ppm(&dir.join(format!("frame_{index:06}.ppm")), img)
+            }
+            FrameSink::Stdout(out) => Ok(out.write_all(img.as_bytes())?),
+        }
+    }
+
+    pub fn finish(&mut self) -> Result<()> {
+        if let FrameSink::Stdout(out) = self {
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes an image as 8-bit RGBA PNG file
+pub fn write_png(path: &Path, img: &RgbaImage) -> Result<()> {
+    write_png_with_text(path, img, &[])
+}
+
+/// Like [write_png] and embeds text as (keyword, text) pairs, e.g. ("Software", "DEISS")
+pub fn write_png_with_text(path: &Path, img: &RgbaImage, text: &[(&str, String)]) -> Result<()> {
+    let file = File::create(path).map_err(|e| eyre!("Failed to create {path:?}: {e}"))?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), img.cols(), img.rows());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (keyword, text) in text {
+        encoder.add_itxt_chunk(keyword.to_string(), text.clone())?;
+    }
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(img.as_bytes())?;
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Writes an image as binary PPM file dropping the alpha channel
+pub fn write_ppm(path: &Path, img: &RgbaImage) -> Result<()> {
+    let file = File::create(path).map_err(|e| eyre!("Failed to create {path:?}: {e}"))?;
+    let mut out = BufWriter::new(file);
+
+    write!(out, "P6\n{} {}\n255\n", img.cols(), img.rows())?;
+    for px in img.as_slice() {
+        out.write_all(&px.0[..3])?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use crate::{
+    audio::{AudioListener, AudioSamples, decode_file},
+    config::Config,
+    headless::FrameSink,
+    painter::{Clock, Painter},
+};
+use eyre::{Result, bail};
+use rodio::Source;
+
+pub struct HeadlessConfig {
+    /// Audio file to visualize
+    pub filename: String,
+
+    /// Number of rendered frames per second of audio
+    pub fps: u32,
+
+    /// Stop after this many frames
+    pub max_frames: Option<u64>,
+}
+
+/// Renders an audio file into a sequence of frames without window, GPU or sound card.
+///
+/// The audio file is decoded and fed to the painter in chunks of [AudioListener::buffer_size]
+/// frames. Frames are rendered at a fixed rate derived from the sample clock: before frame `k` is
+/// rendered all chunks which end before `(k + 1) / fps` seconds into the audio have been delivered.
+///
+/// The painter runs on a [Clock::Fixed] clock so that rendering the same file with the same seed
+/// always produces identical frames.
+pub fn render_headless(
+    config: &Config,
+    headless: &HeadlessConfig,
+    sink: &mut FrameSink,
+) -> Result<u64> {
+    if headless.fps == 0 {
+        bail!("The frame rate must be at least 1 fps");
+    }
+
+    let mut source = decode_file(&headless.filename)?;
+    let sample_rate = source.sample_rate();
+    let channels = source.channels();
+
+    let mut painter =
+        Painter::new(config.paint_shape, Clock::fixed(headless.fps as f32), config.seed);
+    painter.load_presets(&config.preset_dirs)?;
+    if let Some(path) = &config.bookmarks_path {
+        painter.load_bookmarks(path)?;
+    }
+    config.apply(&mut painter.settings);
+    // interleaved samples per chunk
+    let buffer_size = painter.buffer_size(sample_rate) * channels as usize;
+
+    log::info!(
+        "Rendering {} ({sample_rate} Hz, {channels} channels) at {} fps",
+        headless.filename,
+        headless.fps
+    );
+
+    let samples_per_second = sample_rate as u64 * channels as u64;
+
+    let mut fed = 0_u64;
+    let mut frame = 0_u64;
+    'frames: while headless.max_frames.is_none_or(|max| frame < max) {
+        let target = (frame + 1) * samples_per_second / headless.fps as u64;
+
+        while fed + buffer_size as u64 <= target {
+            let chunk = source.by_ref().take(buffer_size).collect::<Vec<_>>();
+            if chunk.len() < buffer_size {
+                break 'frames;
+            }
+            painter.on_samples(&AudioSamples::new(chunk, sample_rate, channels));
+            fed += buffer_size as u64;
+        }
+
+        painter.on_render();
+        sink.write_frame(frame, painter.image())?;
+        frame += 1;
+    }
+
+    sink.finish()?;
+
+    log::info!("Rendered {frame} frames");
+
+    Ok(frame)
+}
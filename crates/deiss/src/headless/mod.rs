@@ -0,0 +1,11 @@
+//! Offline rendering of audio files into frame sequences and offline analysis
+
+mod bench;
+mod frame_sink;
+mod headless_renderer;
+mod tempo_analysis;
+
+pub use bench::*;
+pub use frame_sink::*;
+pub use headless_renderer::*;
+pub use tempo_analysis::*;
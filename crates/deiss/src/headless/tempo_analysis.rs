@@ -0,0 +1,112 @@
+use crate::{
+    audio::{AudioListener, AudioSamples, decode_file},
+    painter::{Clock, Painter},
+};
+use eyre::Result;
+use rodio::Source;
+
+/// State of the tempo tracker at some time into the audio
+#[derive(Debug, Clone)]
+pub struct TempoSample {
+    /// Seconds into the audio
+    pub time: f32,
+    pub bpm: Option<f32>,
+    pub confidence: f32,
+    pub phase: f32,
+}
+
+/// Runs the tempo tracker of the painter over an audio file and samples its state every
+/// `interval` seconds. Audio is fed in the same chunks as during rendering so that the result
+/// matches what the visuals see.
+pub fn analyze_tempo(filename: &str, interval: f32) -> Result<Vec<TempoSample>> {
+    let source = decode_file(filename)?;
+    let sample_rate = source.sample_rate();
+    let channels = source.channels();
+    Ok(analyze_samples(source, sample_rate, channels, interval))
+}
+
+/// [analyze_tempo] of interleaved samples
+fn analyze_samples(
+    mut source: impl Iterator<Item = f32>,
+    sample_rate: u32,
+    channels: u16,
+    interval: f32,
+) -> Vec<TempoSample> {
+    let mut painter = Painter::new((480, 640).into(), Clock::fixed(30.), None);
+    let buffer_size = painter.buffer_size(sample_rate) * channels as usize;
+
+    let mut samples = Vec::new();
+    let mut fed = 0_u64;
+    let mut next_sample = interval;
+    loop {
+        let chunk = source.by_ref().take(buffer_size).collect::<Vec<_>>();
+        if chunk.len() < buffer_size {
+            break;
+        }
+        painter.on_samples(&AudioSamples::new(chunk, sample_rate, channels));
+        fed += buffer_size as u64;
+
+        let time = fed as f32 / (sample_rate as f32 * channels as f32);
+        if time >= next_sample {
+            next_sample += interval;
+            let tempo = &painter.globals.tempo;
+            samples.push(TempoSample {
+                time,
+                bpm: tempo.bpm(),
+                confidence: tempo.confidence(),
+                phase: tempo.phase(),
+            });
+        }
+    }
+
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    /// Mono kick drum at the given tempo
+    fn click_track(bpm: f32, secs: f32, sample_rate: u32) -> Vec<f32> {
+        let period = 60. / bpm;
+        (0..(secs * sample_rate as f32) as usize)
+            .map(|i| {
+                let t = (i as f32 / sample_rate as f32) % period;
+                // pitch falls from 150 to 50 Hz
+                let freq = 50. + 100. * (-t * 40.).exp();
+                0.8 * (-t * 12.).exp() * (t * freq * TAU).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracks_125_bpm() {
+        let bpm = 125.;
+        let interval = 0.1;
+        let samples =
+            analyze_samples(click_track(bpm, 30., 44_100).into_iter(), 44_100, 1, interval);
+        let settled = samples.iter().filter(|s| s.time >= 10.).collect::<Vec<_>>();
+        assert!(!settled.is_empty());
+
+        for s in &settled {
+            let detected = s.bpm.expect("no tempo detected");
+            assert!((detected - bpm).abs() <= 1., "{detected} bpm at {} s", s.time);
+            assert!(s.confidence > 0.5, "confidence {} at {} s", s.confidence, s.time);
+        }
+
+        // the phase moves forward by the beats between two samples
+        let step = interval * bpm / 60.;
+        for w in settled.windows(2) {
+            let advance = (w[1].phase - w[0].phase).rem_euclid(1.);
+            let expected = (w[1].time - w[0].time) / interval * step;
+            assert!(
+                (advance - expected).abs() < 0.05,
+                "phase {} -> {} at {} s",
+                w[0].phase,
+                w[1].phase,
+                w[1].time
+            );
+        }
+    }
+}
@@ -1,31 +1,186 @@
 pub mod audio;
+mod cli;
+mod command;
 pub mod config;
 mod deiss_app;
 pub mod fx;
 pub mod gui;
+pub mod headless;
 pub mod painter;
+pub mod profile;
 pub mod renderer;
 pub mod utils;
 
-use crate::{config::Config, deiss_app::DeissApp};
+use crate::{
+    audio::{default_input_device, input_devices},
+    cli::{Cli, Command},
+    config::Config,
+    deiss_app::DeissApp,
+    headless::{
+        BenchConfig, BenchResult, FrameSink, HeadlessConfig, analyze_tempo, render_headless,
+        run_bench,
+    },
+    painter::{EFFECT_NAMES, Globals, ModeBlueprintLibrary},
+};
+use clap::Parser;
 use eyre::{Result, bail};
+use std::{path::PathBuf, time::Duration};
 use winit::event_loop::{ControlFlow, EventLoop};
 
 fn main() -> Result<()> {
-    println!("{:?}", std::env::args());
-
-    let Some(filename) = std::env::args().skip(1).next() else {
-        bail!("Usage: deiss music.wav/.mp3");
-    };
+    let cli = Cli::parse();
 
     env_logger::init();
 
+    match cli.into_command() {
+        Command::Play(args) => {
+            if args.files.is_empty() {
+                bail!("No audio files given. Usage: deiss play music.wav/.mp3 ...");
+            }
+            let mut config = Config::try_from(&args)?;
+            config.playlist = args.playlist()?;
+            validate_config(&config)?;
+            run_app(config)
+        }
+        Command::Listen(args) => {
+            if let Some(device) = &args.device
+                && !input_devices()?.contains(device)
+            {
+                bail!("Unknown input device '{device}'. Use `deiss list-devices` to see devices.");
+            }
+            let config = Config::try_from(&args)?;
+            validate_config(&config)?;
+            run_app(config)
+        }
+        Command::Render(args) => {
+            let config = Config::try_from(&args)?;
+            validate_config(&config)?;
+
+            let headless =
+                HeadlessConfig { filename: args.file, fps: args.fps, max_frames: args.max_frames };
+
+            let mut sink = FrameSink::new(&args.out, args.format.into())?;
+            render_headless(&config, &headless, &mut sink)?;
+            Ok(())
+        }
+        Command::Tempo(args) => print_tempo(&args.file, args.interval),
+        Command::Bench(args) => {
+            let config = Config::from(&args);
+            let results = run_bench(&config, &BenchConfig::from(&args))?;
+            print_bench(&config, &results);
+            Ok(())
+        }
+        Command::ListModes(args) => {
+            let mut preset_dirs = Config::default().preset_dirs;
+            preset_dirs.extend(args.preset_dirs);
+            list_modes(&preset_dirs)
+        }
+        Command::ListDevices => list_devices(),
+    }
+}
+
+fn run_app(config: Config) -> Result<()> {
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
 
-    let config = Config { filename };
-
     let mut app = DeissApp::new(config);
     event_loop.run_app(&mut app)?;
     Ok(())
 }
+
+fn print_tempo(file: &str, interval: f32) -> Result<()> {
+    let samples = analyze_tempo(file, interval)?;
+    for sample in &samples {
+        match sample.bpm {
+            Some(bpm) => println!(
+                "{:7.1} s  {bpm:6.1} bpm  confidence {:.2}  phase {:.2}",
+                sample.time, sample.confidence, sample.phase
+            ),
+            None => println!("{:7.1} s       - bpm", sample.time),
+        }
+    }
+
+    match samples.last() {
+        Some(sample) if sample.bpm.is_some() => {
+            println!("Tempo: {:.1} bpm (confidence {:.2})", sample.bpm.unwrap(), sample.confidence)
+        }
+        _ => println!("Tempo: unknown"),
+    }
+    Ok(())
+}
+
+fn print_bench(config: &Config, results: &[BenchResult]) {
+    let ms = |d: Duration| d.as_secs_f64() * 1000.;
+    let speedup = |scalar: Duration, parallel: Duration| {
+        scalar.as_secs_f64() / parallel.as_secs_f64().max(1e-9)
+    };
+
+    let (w, h) = (config.paint_shape.cols(), config.paint_shape.rows());
+    println!("{w}x{h}, {} threads", rayon::current_num_threads());
+    println!("Mode   bake scalar  parallel  speedup   process scalar  parallel  speedup");
+    for r in results {
+        println!(
+            "{:>4}   {:>8.1} ms {:>6.1} ms {:>7.1}x   {:>11.2} ms {:>6.2} ms {:>7.1}x",
+            r.mode.0,
+            ms(r.bake_scalar),
+            ms(r.bake_parallel),
+            speedup(r.bake_scalar, r.bake_parallel),
+            ms(r.process_scalar),
+            ms(r.process_parallel),
+            speedup(r.process_scalar, r.process_parallel),
+        );
+    }
+    println!("All parallel results match the scalar versions");
+}
+
+/// Built-in modes together with the presets of the given directories
+fn load_library(preset_dirs: &[PathBuf]) -> Result<ModeBlueprintLibrary> {
+    let mut library = ModeBlueprintLibrary::new(&mut Globals::default());
+    for dir in preset_dirs {
+        library.add_presets(dir)?;
+    }
+    Ok(library)
+}
+
+fn validate_config(config: &Config) -> Result<()> {
+    let library = load_library(&config.preset_dirs)?;
+    if let Some(mode) = config.mode
+        && !library.contains(mode)
+    {
+        bail!("Unknown mode {}. Use `deiss list-modes` to see available modes.", mode.0);
+    }
+    Ok(())
+}
+
+fn list_devices() -> Result<()> {
+    let default = default_input_device();
+    for name in input_devices()? {
+        let marker = if default.as_ref() == Some(&name) { " (default)" } else { "" };
+        println!("{name}{marker}");
+    }
+    Ok(())
+}
+
+fn list_modes(preset_dirs: &[PathBuf]) -> Result<()> {
+    let library = load_library(preset_dirs)?;
+    for mode in library.mode_ids() {
+        let blueprint = &library[mode];
+        let effects = EFFECT_NAMES
+            .iter()
+            .zip(blueprint.effect_freq.as_array())
+            .filter(|(_, freq)| **freq > 0)
+            .map(|(name, freq)| format!("{name} {freq}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "Mode {:>2}: {}-{} effects ({effects}), solar max {}, center dwindle {}{}",
+            mode.0,
+            blueprint.effect_count[0],
+            blueprint.effect_count[1],
+            blueprint.solar_max,
+            blueprint.center_dwindle,
+            if blueprint.motion_dampened { ", dampened motion" } else { "" },
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use crate::{painter::*, utils::*};
+use eyre::{Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Exact look of a mode which can be shown again.
+///
+/// Stores the parameters of a [WarpSpec]. The transform is generated again from the state the
+/// random number generator had when it was generated, so a bookmark of a preset changes if the
+/// preset is edited.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub name: String,
+    pub mode: ModeId,
+    pub waveform: WaveformId,
+    pub effects: Effects,
+
+    /// Offset of the center from the middle of the image in reference pixels
+    pub center: Vec2f,
+
+    pub weightsum: f32,
+    pub damping: f32,
+
+    /// State of the random number generator the transform is generated with
+    pub transform_seed: u64,
+
+    /// Color cycling frequencies, see [Settings::gf]
+    pub gf: [f32; 6],
+}
+
+impl Bookmark {
+    /// Bookmarks a spec which is shown with the colors of `s`
+    pub fn new(name: String, spec: &WarpSpec, s: &Settings) -> Self {
+        Self {
+            name,
+            mode: spec.mode,
+            waveform: spec.waveform,
+            effects: spec.effects.clone(),
+            center: center_offset(&spec.settings, spec.center),
+            weightsum: spec.weightsum,
+            damping: spec.damping,
+            transform_seed: spec.tf_rand.state(),
+            gf: s.gf,
+        }
+    }
+
+    /// Spec which shows the bookmark at the resolution of `s`
+    pub fn to_spec(
+        &self,
+        s: &Settings,
+        fx: &ModeBlueprintLibrary,
+        g: &Globals,
+    ) -> Result<WarpSpec> {
+        if !fx.contains(self.mode) {
+            bail!("Mode {} of bookmark '{}' doesn't exist", self.mode.0, self.name);
+        }
+
+        let mut rand = Minstd::from_state(self.transform_seed);
+        let tf = fx[self.mode].generate_transform(&mut rand, &TransformInputs::new(g));
+        let center = center_from_offset(s, self.center);
+        let center = Vec2::new(
+            center.x.clamp(0, s.fxw as i32 - 1),
+            center.y.clamp(s.y_roi.min as i32, s.y_roi.max as i32 - 1),
+        );
+
+        Ok(WarpSpec {
+            settings: s.clone(),
+            effects: self.effects.clone(),
+            mode: self.mode,
+            waveform: self.waveform,
+            center,
+            weightsum: self.weightsum,
+            damping: self.damping,
+            tf,
+            tf_rand: Minstd::from_state(self.transform_seed),
+            gf: Some(self.gf),
+        })
+    }
+}
+
+/// Content of a favorites file, a list of `[[bookmark]]` tables
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BookmarkFile {
+    #[serde(default)]
+    bookmark: Vec<Bookmark>,
+}
+
+/// Loads the bookmarks of a favorites file. A missing file has no bookmarks.
+pub fn load_bookmarks(path: &Path) -> Result<Vec<Bookmark>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let text =
+        fs::read_to_string(path).map_err(|e| eyre!("Failed to read bookmarks {path:?}: {e}"))?;
+    let file = toml::from_str::<BookmarkFile>(&text)
+        .map_err(|e| eyre!("Invalid bookmarks {path:?}: {e}"))?;
+    Ok(file.bookmark)
+}
+
+/// Writes bookmarks to a favorites file, creating its directory if needed
+pub fn save_bookmarks(path: &Path, bookmarks: &[Bookmark]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| eyre!("Failed to create directory {dir:?}: {e}"))?;
+    }
+    let file = BookmarkFile { bookmark: bookmarks.to_vec() };
+    let text = toml::to_string(&file).map_err(|e| eyre!("Failed to serialize bookmarks: {e}"))?;
+    fs::write(path, text).map_err(|e| eyre!("Failed to write bookmarks {path:?}: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bookmark_bakes_the_same_map() {
+        let mut painter = Painter::new((240, 320).into(), Clock::fixed(30.), Some(5));
+        let s = &painter.settings;
+        let g = &mut painter.globals;
+        for mode in painter.library.mode_ids() {
+            let spec = WarpSpec::generate_mode(mode, s, &painter.library, g);
+            let bookmark = Bookmark::new("b".into(), &spec, s);
+            let recalled = bookmark.to_spec(s, &painter.library, g).unwrap();
+            assert_eq!(recalled.center, spec.center, "center of mode {}", mode.0);
+
+            let bake_spec = |spec: &WarpSpec| {
+                bake(s, spec.center.cast(), spec.weightsum, spec.damping, &spec.tf).map
+            };
+            let (expected, actual) = (bake_spec(&spec), bake_spec(&recalled));
+            assert!(actual.as_slice() == expected.as_slice(), "map of mode {}", mode.0);
+        }
+    }
+
+    #[test]
+    fn bookmarks_survive_saving() {
+        let mut painter = Painter::new((480, 640).into(), Clock::fixed(30.), Some(5));
+        let s = &painter.settings;
+        let g = &mut painter.globals;
+        let bookmarks = (1..=3)
+            .map(|mode| {
+                let spec = WarpSpec::generate_mode(ModeId(mode), s, &painter.library, g);
+                Bookmark::new(format!("mode {mode}"), &spec, s)
+            })
+            .collect::<Vec<_>>();
+
+        let dir = TempDir::new("bookmarks");
+        let path = dir.0.join("favorites/bookmarks.toml");
+        assert!(load_bookmarks(&path).unwrap().is_empty());
+        save_bookmarks(&path, &bookmarks).unwrap();
+        assert_eq!(load_bookmarks(&path).unwrap(), bookmarks);
+    }
+}
@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+/// Time source driving mode switches and frame rate estimation
+#[derive(Debug, Clone)]
+pub enum Clock {
+    /// Wall-clock time since the clock was created
+    Realtime { start: Instant },
+
+    /// Time advances by exactly `1 / fps` with every rendered frame. Together with a fixed seed
+    /// this makes rendering fully deterministic.
+    Fixed { fps: f32, frames: u64 },
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::realtime()
+    }
+}
+
+impl Clock {
+    pub fn realtime() -> Self {
+        Clock::Realtime { start: Instant::now() }
+    }
+
+    /// Panics if `fps` isn't positive
+    pub fn fixed(fps: f32) -> Self {
+        assert!(fps > 0., "Fixed clock needs a positive frame rate, got {fps}");
+        Clock::Fixed { fps, frames: 0 }
+    }
+
+    /// True if time only advances with rendered frames
+    pub fn is_deterministic(&self) -> bool {
+        matches!(self, Clock::Fixed { .. })
+    }
+
+    /// Called once per rendered frame
+    pub fn tick(&mut self) {
+        if let Clock::Fixed { frames, .. } = self {
+            *frames += 1;
+        }
+    }
+
+    /// Time elapsed since the clock was started
+    pub fn now(&self) -> Duration {
+        match self {
+            Clock::Realtime { start } => start.elapsed(),
+            Clock::Fixed { fps, frames } => Duration::from_secs_f64(*frames as f64 / *fps as f64),
+        }
+    }
+}
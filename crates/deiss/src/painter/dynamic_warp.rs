@@ -0,0 +1,84 @@
+use crate::{painter::*, utils::*};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DynamicWarpSettings {
+    /// How much the image zooms in on a kick (0.0 = off). 0.05 moves every pixel 5% towards the
+    /// center.
+    pub zoom_pulse: f32,
+
+    /// Dynamic transforms are evaluated again every this many frames
+    pub rebake_frames: u32,
+
+    /// Size of the grid cells in pixels on which dynamic transforms are evaluated
+    pub grid_cell: u32,
+}
+
+impl Default for DynamicWarpSettings {
+    fn default() -> Self {
+        Self { zoom_pulse: 0., rebake_frames: 2, grid_cell: 8 }
+    }
+}
+
+/// Zoom below which the baked map is used unchanged
+const MIN_ZOOM: f32 = 1e-4;
+
+/// Warp map which follows the music while a mode is shown.
+///
+/// Dynamic transforms, see [GeneralPixelTransform::is_dynamic], are evaluated on a coarse grid
+/// every few frames. Every frame the source positions are then pulled towards the center while
+/// a kick is active.
+#[derive(Default)]
+pub struct DynamicWarp {
+    map: Option<WarpMap>,
+
+    /// Field of the last re-bake of a dynamic transform
+    field: Option<SourceField>,
+
+    /// Frames since the last re-bake
+    frames: u32,
+}
+
+impl DynamicWarp {
+    /// Forgets the map of the previous mode
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Takes the last modulated map, e.g. to cross-fade from it
+    pub fn take_map(&mut self) -> Option<WarpMap> {
+        self.field = None;
+        self.map.take()
+    }
+
+    /// Updates the map for the next frame. Returns None if the baked map can be used unchanged.
+    pub fn step(
+        &mut self,
+        s: &Settings,
+        spec: &mut WarpSpec,
+        baked: &BakedWarp,
+        g: &Globals,
+    ) -> Option<&WarpMap> {
+        let zoom = (s.dynamic.zoom_pulse * g.kick).clamp(0., 0.5);
+        let dynamic = spec.tf.is_dynamic();
+        if !dynamic && zoom < MIN_ZOOM {
+            self.map = None;
+            return None;
+        }
+
+        if dynamic && (self.field.is_none() || self.frames >= s.dynamic.rebake_frames) {
+            self.frames = 0;
+            spec.tf.set_inputs(&TransformInputs::new(g));
+            let cell = s.dynamic.grid_cell.max(1);
+            self.field =
+                Some(bake_field_coarse(s, spec.center.cast(), spec.damping, &spec.tf, cell));
+        }
+        self.frames += 1;
+
+        let field = self.field.as_ref().filter(|_| dynamic).unwrap_or(&baked.field);
+        let center: Vec2f = spec.center.cast();
+        let map = quantize(s, spec.weightsum, field, |p| center + (p - center) * (1. - zoom));
+        Some(self.map.insert(map))
+    }
+}
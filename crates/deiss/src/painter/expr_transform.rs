@@ -0,0 +1,85 @@
+use crate::{painter::*, utils::*};
+use serde::Deserialize;
+
+/// Variables available in pixel expressions, in the order of their values
+pub const PIXEL_EXPR_VARS: [&str; 14] = [
+    "x", "y", "r", "theta", "cx", "cy", "w", "h", "time", "bass", "mid", "treble", "phase", "kick",
+];
+
+/// Index of the first variable which is the same for all pixels but changes over time
+const FIRST_INPUT_VAR: usize = 8;
+
+/// Expression which computes one source coordinate of a pixel.
+///
+/// `x` and `y` are the pixel coordinates, `r` and `theta` the polar coordinates around the center
+/// `cx`, `cy` and `w`, `h` the size of the image. Coordinates are in reference pixels, i.e. `w` is
+/// always 640, so that presets look the same at every resolution. The remaining variables are the
+/// fields of [TransformInputs]. Expressions which use them are re-baked while the mode is shown.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct PixelExpr(Expr);
+
+impl TryFrom<String> for PixelExpr {
+    type Error = ExprError;
+
+    fn try_from(src: String) -> Result<Self, Self::Error> {
+        Expr::compile(&src, &PIXEL_EXPR_VARS).map(PixelExpr)
+    }
+}
+
+/// Motion field where the source of each pixel is given by two expressions
+#[derive(Debug, Clone)]
+pub struct ExprTransform {
+    x: PixelExpr,
+    y: PixelExpr,
+    inputs: TransformInputs,
+}
+
+impl ExprTransform {
+    pub fn new(x: PixelExpr, y: PixelExpr, inputs: TransformInputs) -> Self {
+        Self { x, y, inputs }
+    }
+}
+
+impl GeneralPixelTransform for ExprTransform {
+    fn transform(&self, p: Vec2f, c: Vec2f, s: Vec2f) -> Vec2f {
+        // evaluate in reference pixels so that presets look the same at every resolution
+        let k = res_scale(s);
+        let (p, c, s) = (p * (1. / k), c * (1. / k), s * (1. / k));
+        let d = p - c;
+        let vars = [
+            p.x,
+            p.y,
+            d.norm(),
+            d.y.atan2(d.x),
+            c.x,
+            c.y,
+            s.x,
+            s.y,
+            self.inputs.time,
+            self.inputs.bass,
+            self.inputs.mid,
+            self.inputs.treble,
+            self.inputs.phase,
+            self.inputs.kick,
+        ];
+        let q = Vec2f::new(self.x.0.eval(&vars), self.y.0.eval(&vars));
+
+        // keep the pixel where it is instead of sampling far outside of the image
+        let q = if q.x.is_finite() && q.y.is_finite() {
+            Vec2f::new(q.x.clamp(-s.x, 2. * s.x), q.y.clamp(-s.y, 2. * s.y))
+        } else {
+            p
+        };
+        q * k
+    }
+
+    fn is_dynamic(&self) -> bool {
+        (FIRST_INPUT_VAR..PIXEL_EXPR_VARS.len())
+            .any(|i| self.x.0.uses_var(i) || self.y.0.uses_var(i))
+    }
+
+    fn set_inputs(&mut self, inputs: &TransformInputs) {
+        self.inputs = inputs.clone();
+    }
+}
@@ -2,7 +2,7 @@ use crate::{fx::Chasers, painter::*, utils::Minstd};
 use core::ops;
 use std::{
     sync::{Arc, Mutex},
-    time::Instant,
+    time::Duration,
 };
 
 #[derive(Debug, Default)]
@@ -19,8 +19,18 @@ pub struct Globals {
     pub avg_vol_wide: f32,
     pub avg_vol_peaks: f32,
     pub volume_sum: u64,
+    /// Seconds of audio in a row which were below the silence level
+    pub silence: f32,
+    /// True while the input is silent or no audio arrives
+    pub sound_empty: bool,
+    /// 1.0 at the start of a beat decaying quickly to 0.0
+    pub kick: f32,
+    /// Value of [Self::is_beat] for the last analyzed audio
+    pub was_beat: bool,
     pub suggested_dampening: f32,
-    pub fourier: RunningFourier,
+    pub spectrum: Spectrum,
+    pub tempo: Tempo,
+    pub clock: Clock,
     pub fps: Fps,
     pub fps_at_last_mode_switch: f32,
     pub time_scale: f32,
@@ -30,6 +40,34 @@ pub struct Globals {
     pub sound_buffer: SoundBuffer,
 }
 
+impl Globals {
+    /// Forgets everything learned from past audio samples so that the visuals don't react to audio
+    /// which is no longer playing, e.g. after seeking
+    pub fn reset_analysis(&mut self) {
+        self.last_frame_v = 0.;
+        self.last_frame_slope = 0.;
+        self.vol = VolBuffer::default();
+        self.vol_narrow = VolBuffer::default();
+        self.avg_vol_narrow = 0.;
+        self.avg_vol = 0.;
+        self.avg_vol_wide = 0.;
+        self.avg_vol_peaks = 0.;
+        self.silence = 0.;
+        self.kick = 0.;
+        self.was_beat = false;
+        self.spectrum.reset();
+        self.tempo.reset();
+        self.sound_buffer = SoundBuffer::default();
+    }
+
+    /// True while the short-term volume is more than one standard deviation above the mean of
+    /// [Self::vol_narrow], i.e. during a beat
+    pub fn is_beat(&self) -> bool {
+        let mean = self.vol_narrow.mean();
+        mean > 1. && self.avg_vol_narrow > mean + self.vol_narrow.std_dev()
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct SoundBuffer(Vec<f32>);
 
@@ -61,6 +99,26 @@ impl SoundBuffer {
     pub fn rch(&self, i: usize) -> f32 {
         self.0[2 * i + 1]
     }
+
+    /// Left channel at a fractional frame index, interpolated linearly. Indices past the end
+    /// return the last frame.
+    pub fn lch_at(&self, x: f32) -> f32 {
+        self.interpolate(x, 0)
+    }
+
+    /// Right channel at a fractional frame index, see [Self::lch_at]
+    pub fn rch_at(&self, x: f32) -> f32 {
+        self.interpolate(x, 1)
+    }
+
+    fn interpolate(&self, x: f32, channel: usize) -> f32 {
+        let last = self.0.len() / 2 - 1;
+        let i0 = (x.max(0.) as usize).min(last);
+        let i1 = (i0 + 1).min(last);
+        let f = (x - i0 as f32).clamp(0., 1.);
+        let (v0, v1) = (self.0[2 * i0 + channel], self.0[2 * i1 + channel]);
+        v0 + (v1 - v0) * f
+    }
 }
 
 impl ops::Index<usize> for SoundBuffer {
@@ -71,27 +129,22 @@ impl ops::Index<usize> for SoundBuffer {
     }
 }
 
-#[derive(Debug)]
+/// Measures frames per second between resets using the time reported by [Clock]
+#[derive(Debug, Default)]
 pub struct Fps {
-    start: Instant,
+    start: Duration,
     frames: u32,
 }
 
-impl Default for Fps {
-    fn default() -> Self {
-        Self { start: Instant::now(), frames: 0 }
-    }
-}
-
 impl Fps {
     pub fn step(&mut self) {
         self.frames += 1;
     }
 
-    pub fn reset(&mut self) -> f32 {
-        let dt = (Instant::now() - self.start).as_secs_f32();
-        let fps = self.frames as f32 / dt;
-        self.start = Instant::now();
+    pub fn reset(&mut self, now: Duration) -> f32 {
+        let dt = (now - self.start).as_secs_f32();
+        let fps = if self.frames == 0 || dt <= 0. { 0. } else { self.frames as f32 / dt };
+        self.start = now;
         self.frames = 0;
         fps
     }
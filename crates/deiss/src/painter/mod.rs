@@ -1,23 +1,41 @@
+mod bookmark;
+mod clock;
+mod dynamic_warp;
+mod expr_transform;
 mod globals;
 mod mode_blueprint;
 mod mode_blueprint_library;
 mod mode_id;
 mod mode_pixel_transforms;
+mod mode_preset;
+mod mode_switch;
 mod painter;
 mod pixel_transform;
 mod settings;
+mod spectrum;
+mod tempo;
+mod transition;
 mod warp;
 mod warp_hub;
 mod wave;
 
+pub use bookmark::*;
+pub use clock::*;
+pub use dynamic_warp::*;
+pub use expr_transform::*;
 pub use globals::*;
 pub use mode_blueprint::*;
 pub use mode_blueprint_library::*;
 pub use mode_id::*;
 pub use mode_pixel_transforms::*;
+pub use mode_preset::*;
+pub use mode_switch::*;
 pub use painter::*;
 pub use pixel_transform::*;
 pub use settings::*;
+pub use spectrum::*;
+pub use tempo::*;
+pub use transition::*;
 pub use warp::*;
 pub use warp_hub::*;
 pub use wave::*;
@@ -1,5 +1,6 @@
 use crate::{painter::*, utils::Minstd};
 use core::ops;
+use serde::{Deserialize, Serialize};
 
 pub struct ModeBlueprint {
     pub effect_freq: EffectFreq,
@@ -11,8 +12,8 @@ pub struct ModeBlueprint {
 }
 
 impl ModeBlueprint {
-    pub fn generate_transform(&self, rand: &mut Minstd) -> AnyTransform {
-        self.tf_gen.generate_transform(rand)
+    pub fn generate_transform(&self, rand: &mut Minstd, inputs: &TransformInputs) -> AnyTransform {
+        self.tf_gen.generate_transform(rand, inputs)
     }
 }
 
@@ -31,10 +32,18 @@ pub enum EffectKind {
 
 pub const NUM_EFFECTS: usize = 8;
 
+pub const EFFECT_NAMES: [&str; NUM_EFFECTS] =
+    ["Chasers", "Bar", "Dots", "Solar", "Grid", "Nuclide", "Shade", "Spectral"];
+
 #[derive(Debug, Clone)]
 pub struct EffectFreq([u32; NUM_EFFECTS]);
 
 impl EffectFreq {
+    /// Base frequency for each effect in 1/1000
+    pub fn as_array(&self) -> &[u32; NUM_EFFECTS] {
+        &self.0
+    }
+
     pub fn sample(&self, (min, max): (usize, usize), rand: &mut Minstd) -> Effects {
         let mut effect = Effects(self.0.map(|thresh| rand.next_idx(1000) < (thresh * 7) / 10));
 
@@ -95,7 +104,9 @@ impl ops::IndexMut<EffectKind> for EffectFreq {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Effects shown by a mode. Stored as a list of [EFFECT_NAMES].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(into = "Vec<String>", try_from = "Vec<String>")]
 pub struct Effects([bool; NUM_EFFECTS]);
 
 impl Effects {
@@ -104,6 +115,33 @@ impl Effects {
     }
 }
 
+impl From<Effects> for Vec<String> {
+    fn from(effects: Effects) -> Self {
+        EFFECT_NAMES
+            .iter()
+            .zip(effects.0)
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+}
+
+impl TryFrom<Vec<String>> for Effects {
+    type Error = String;
+
+    fn try_from(names: Vec<String>) -> Result<Self, Self::Error> {
+        let mut effects = Effects([false; NUM_EFFECTS]);
+        for name in names {
+            let i = EFFECT_NAMES
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(&name))
+                .ok_or_else(|| format!("unknown effect '{name}'"))?;
+            effects.0[i] = true;
+        }
+        Ok(effects)
+    }
+}
+
 impl ops::Index<usize> for Effects {
     type Output = bool;
 
@@ -1,6 +1,7 @@
 use crate::{painter::*, utils::Minstd};
 use core::ops;
-use std::collections::HashMap;
+use eyre::Result;
+use std::{collections::HashMap, path::Path};
 
 pub struct ModeBlueprintLibrary {
     pub mode_info: HashMap<ModeId, ModeBlueprint>,
@@ -13,7 +14,7 @@ impl ModeBlueprintLibrary {
         mode_info.insert(
             ModeId(1),
             ModeBlueprint {
-                effect_freq: [220, 150, 10, 680, 4, 170, 400, 0].into(),
+                effect_freq: [220, 150, 10, 680, 4, 170, 400, 120].into(),
                 solar_max: 800,
                 center_dwindle: 1.0,
                 effect_count: [1, 2],
@@ -37,7 +38,7 @@ impl ModeBlueprintLibrary {
         mode_info.insert(
             ModeId(3),
             ModeBlueprint {
-                effect_freq: [100, 100, 100, 500, 10, 0, 300, 0].into(),
+                effect_freq: [100, 100, 100, 500, 10, 0, 300, 150].into(),
                 solar_max: 60,
                 center_dwindle: 0.99,
                 effect_count: [1, 2],
@@ -88,7 +89,7 @@ impl ModeBlueprintLibrary {
         mode_info.insert(
             ModeId(7),
             ModeBlueprint {
-                effect_freq: [50, 200, 0, 300, 0, 600, 350, 0].into(),
+                effect_freq: [50, 200, 0, 300, 0, 600, 350, 100].into(),
                 solar_max: 65,
                 center_dwindle: 0.985,
                 effect_count: [1, 2],
@@ -112,7 +113,7 @@ impl ModeBlueprintLibrary {
         mode_info.insert(
             ModeId(9),
             ModeBlueprint {
-                effect_freq: [450, 200, 50, 200, 0, 100, 200, 0].into(),
+                effect_freq: [450, 200, 50, 200, 0, 100, 200, 150].into(),
                 solar_max: 50,
                 center_dwindle: 0.985,
                 effect_count: [1, 2],
@@ -136,7 +137,7 @@ impl ModeBlueprintLibrary {
         mode_info.insert(
             ModeId(11),
             ModeBlueprint {
-                effect_freq: [360, 200, 230, 550, 10, 330, 150, 0].into(),
+                effect_freq: [360, 200, 230, 550, 10, 330, 150, 200].into(),
                 solar_max: 750,
                 center_dwindle: 1.0,
                 effect_count: [0, 4],
@@ -231,6 +232,31 @@ impl ModeBlueprintLibrary {
 
         Self { mode_info }
     }
+
+    /// Adds the presets of a directory to the library. Presets replace built-in modes with the
+    /// same id. Returns the ids of the added modes.
+    pub fn add_presets(&mut self, dir: &Path) -> Result<Vec<ModeId>> {
+        let mut ids = Vec::new();
+        for preset in load_presets(dir)? {
+            let mode = ModeId(preset.mode);
+            if self.mode_info.insert(mode, preset.into_blueprint()).is_some() {
+                log::info!("Preset in {dir:?} replaces mode {}", mode.0);
+            }
+            ids.push(mode);
+        }
+        Ok(ids)
+    }
+
+    pub fn contains(&self, mode: ModeId) -> bool {
+        self.mode_info.contains_key(&mode)
+    }
+
+    /// All modes in the library in ascending order
+    pub fn mode_ids(&self) -> Vec<ModeId> {
+        let mut ids = self.mode_info.keys().copied().collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
 }
 
 impl ops::Index<ModeId> for ModeBlueprintLibrary {
@@ -242,14 +268,15 @@ impl ops::Index<ModeId> for ModeBlueprintLibrary {
 }
 
 pub trait GenerateTransform: 'static + Send + Sync {
-    fn generate_transform(&self, rand: &mut Minstd) -> AnyTransform;
+    fn generate_transform(&self, rand: &mut Minstd, inputs: &TransformInputs) -> AnyTransform;
 }
 
+/// Built-in modes only depend on the random number generator
 impl<F> GenerateTransform for F
 where
     F: 'static + Send + Sync + for<'a> Fn(&'a mut Minstd) -> AnyTransform,
 {
-    fn generate_transform(&self, rand: &mut Minstd) -> AnyTransform {
+    fn generate_transform(&self, rand: &mut Minstd, _: &TransformInputs) -> AnyTransform {
         (self)(rand)
     }
 }
@@ -1,13 +1,16 @@
 use crate::{
     painter::{
-        CenterTransform, DitherTurnScaleTransform, GeneralPixelTransform, ScaleF,
-        TurnScaleTransform, TurnVarScaleTransform, YRoi,
+        CenterTransform, DitherTurnScaleTransform, ExprTransform, GeneralPixelTransform,
+        PresetScale, REFERENCE_HEIGHT, REFERENCE_WIDTH, REFERENCE_Y_ROI, ScaleF, TransformInputs,
+        TurnScaleTransform, TurnVarScaleTransform, YRoi, res_scale,
     },
     utils::*,
 };
 
-const RMULT: f32 = 1.0; // 640/FXW
-const PROTECTIVE_FACTOR: f32 = 1.0; // min(640/FWX, 1)
+/// Damping of the zoom of radially varying modes. The original used `min(640 / FXW, 1)` which
+/// slowed the zoom down at high resolutions. Transforms work in reference pixels instead so that
+/// the motion is the same at every resolution.
+const PROTECTIVE_FACTOR: f32 = 1.0;
 
 #[derive(Debug, Clone)]
 pub struct Marked<T, const N: usize>(T);
@@ -79,7 +82,7 @@ pub struct Mode4Scale;
 
 impl ScaleF for Mode4Scale {
     fn scale(&self, p: Vec2f) -> f32 {
-        let r = p.norm() * RMULT;
+        let r = p.norm();
         0.9 + r * 0.0025 * 0.14
     }
 }
@@ -108,7 +111,7 @@ pub struct Mode5Scale {
 
 impl ScaleF for Mode5Scale {
     fn scale(&self, p: Vec2f) -> f32 {
-        let mut r = p.norm() * RMULT / 200.;
+        let mut r = p.norm() / 200.;
 
         if self.has_nuclide_effect {
             r = r.sqrt();
@@ -125,18 +128,25 @@ impl ScaleF for Mode5Scale {
 /// Mode 6 uses custom motion vectors
 #[derive(Debug, Clone)]
 pub struct Mode6Tf {
+    /// Centers of the vectors in reference pixels. They are stretched to the image so that they
+    /// cover the same area at every aspect ratio.
     c: [Vec2f; 5],
     ctype: [u32; 5],
     c0: [Vec2f; 5],
 }
 
 impl GeneralPixelTransform for Mode6Tf {
-    fn transform(&self, p: Vec2f, _: Vec2f, _: Vec2f) -> Vec2f {
+    fn transform(&self, p: Vec2f, _: Vec2f, shape: Vec2f) -> Vec2f {
+        let k = res_scale(shape);
+        let kx = shape.x / REFERENCE_WIDTH as f32;
+        let ky = shape.y / REFERENCE_HEIGHT as f32;
+
         let mut t = Vec2f::new(0., 0.);
         let mut f = 0.;
 
         for n in 0..5 {
-            let dp = self.c[n] - p;
+            let c = Vec2f::new(self.c[n].x * kx, self.c[n].y * ky);
+            let dp = (c - p) * (1. / k);
             let dp_norm_sq = dp.norm_squared();
             let d = 1. / (dp_norm_sq + 0.1);
             f += d;
@@ -159,13 +169,13 @@ impl GeneralPixelTransform for Mode6Tf {
 
         let t_scale = if f > 0.000001 { 1.9 / f } else { 0. };
 
-        p + t * t_scale + Vec2f::new(-0.1, 0.6)
+        p + t * (t_scale * k) + Vec2f::new(-0.1, 0.6) * k
     }
 }
 
 pub fn mode_6_tf(rand: &mut Minstd) -> Mode6Tf {
-    const FXW: u32 = 640; // FIXME
-    const YROI: YRoi = YRoi { min: 90, max: 480 - 90 }; // FIXME
+    const FXW: u32 = REFERENCE_WIDTH;
+    const YROI: YRoi = REFERENCE_Y_ROI;
 
     Mode6Tf {
         c: core::array::from_fn(|_| {
@@ -209,7 +219,7 @@ pub struct Mode7Scale {
 
 impl ScaleF for Mode7Scale {
     fn scale(&self, p: Vec2f) -> f32 {
-        let r = p.norm() * RMULT * self.f2;
+        let r = p.norm() * self.f2;
         let scale = (self.f1 - r - 1.) * PROTECTIVE_FACTOR + 1.;
         let idx = (p.x + 1000.) as usize + (p.y + 1000.) as usize * 2000;
         scale + self.rand_array[idx % self.rand_array.len()]
@@ -233,7 +243,7 @@ pub struct Mode8Scale {
 
 impl ScaleF for Mode8Scale {
     fn scale(&self, p: Vec2f) -> f32 {
-        let r = p.norm() * RMULT;
+        let r = p.norm();
         0.85 + 0.1 * (self.f1 * r.sqrt()).sin()
     }
 }
@@ -257,7 +267,7 @@ pub struct Mode9Scale {
 
 impl ScaleF for Mode9Scale {
     fn scale(&self, p: Vec2f) -> f32 {
-        let r = p.norm() * self.f2 * RMULT;
+        let r = p.norm() * self.f2;
         (self.f1 - r - 1.) * PROTECTIVE_FACTOR + 1.
     }
 }
@@ -301,8 +311,9 @@ pub fn mode_11_tf(rand: &mut Minstd) -> Mode11Tf {
 pub struct Mode12Tf;
 
 impl GeneralPixelTransform for Mode12Tf {
-    fn transform(&self, p: Vec2f, center: Vec2f, _: Vec2f) -> Vec2f {
-        let nx = p.x - center.x;
+    fn transform(&self, p: Vec2f, center: Vec2f, shape: Vec2f) -> Vec2f {
+        let k = res_scale(shape);
+        let nx = (p.x - center.x) * (1. / k);
         let dx = if nx < -0.5 {
             -(-nx).sqrt() + 0.9
         } else if nx > 0.5 {
@@ -310,7 +321,7 @@ impl GeneralPixelTransform for Mode12Tf {
         } else {
             0.
         };
-        Vec2f::new(center.x + dx, p.y)
+        Vec2f::new(center.x + dx * k, p.y)
     }
 }
 
@@ -333,6 +344,18 @@ macro_rules! define_transform_enum {
                     $(Self::$variant(tf) => tf.transform(p, c, s),)+
                 }
             }
+
+            fn is_dynamic(&self) -> bool {
+                match self {
+                    $(Self::$variant(tf) => tf.is_dynamic(),)+
+                }
+            }
+
+            fn set_inputs(&mut self, inputs: &TransformInputs) {
+                match self {
+                    $(Self::$variant(tf) => tf.set_inputs(inputs),)+
+                }
+            }
         }
 
         $(
@@ -358,4 +381,8 @@ define_transform_enum!(AnyTransform {
     Mode10(Mode10Tf),
     Mode11(Mode11Tf),
     Mode12(Mode12Tf),
+    PresetTurnScale(CenterTransform<TurnScaleTransform>),
+    PresetDitherTurnScale(DitherTurnScaleTransform),
+    PresetTurnVarScale(CenterTransform<TurnVarScaleTransform<PresetScale>>),
+    Expr(ExprTransform),
 });
@@ -0,0 +1,344 @@
+use crate::{painter::*, utils::*};
+use eyre::{Result, eyre};
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Mode blueprint described in a TOML preset file.
+///
+/// ```toml
+/// mode = 25
+/// effect_count = [1, 2]
+/// solar_max = 60
+/// center_dwindle = 0.98
+///
+/// [effect_freq]
+/// chasers = 400
+/// dots = 300
+///
+/// [transform]
+/// kind = "turn_var_scale"
+/// turn = [0.01, 0.03]
+/// scale = [0.9, 0.94]
+/// radial = 0.0003
+/// ```
+///
+/// Unlike the built-in modes the effect frequencies are used as given.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModePreset {
+    /// Mode id. A preset with the id of a built-in mode replaces it.
+    pub mode: u32,
+
+    #[serde(default)]
+    pub effect_freq: EffectFreqPreset,
+
+    #[serde(default)]
+    pub solar_max: u32,
+
+    #[serde(default = "default_center_dwindle")]
+    pub center_dwindle: f32,
+
+    pub effect_count: [u32; 2],
+
+    #[serde(default = "default_motion_dampened")]
+    pub motion_dampened: bool,
+
+    pub transform: TransformPreset,
+}
+
+fn default_center_dwindle() -> f32 {
+    1.0
+}
+
+fn default_motion_dampened() -> bool {
+    true
+}
+
+/// Frequency of each effect in 1/1000. Missing effects never show.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EffectFreqPreset {
+    pub chasers: u32,
+    pub bar: u32,
+    pub dots: u32,
+    pub solar: u32,
+    pub grid: u32,
+    pub nuclide: u32,
+    pub shade: u32,
+    pub spectral: u32,
+}
+
+impl EffectFreqPreset {
+    fn as_array(&self) -> [u32; NUM_EFFECTS] {
+        [
+            self.chasers,
+            self.bar,
+            self.dots,
+            self.solar,
+            self.grid,
+            self.nuclide,
+            self.shade,
+            self.spectral,
+        ]
+    }
+}
+
+/// Parametric motion field. Turns are in the units of the original modes: they are scaled by 0.6
+/// and turn in a random direction.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TransformPreset {
+    /// Rotates and scales around the center, see [TurnScaleTransform]
+    TurnScale { scale: ParamRange, turn: ParamRange },
+
+    /// Alternates between two turn-scale transforms in a checkerboard pattern, see
+    /// [DitherTurnScaleTransform]
+    DitherTurnScale { scale: [ParamRange; 2], turn: [ParamRange; 2] },
+
+    /// Rotates and scales by `scale + radial * r` where `r` is the distance to the center, see
+    /// [TurnVarScaleTransform]
+    TurnVarScale {
+        scale: ParamRange,
+        turn: ParamRange,
+        #[serde(default)]
+        radial: ParamRange,
+    },
+
+    /// Source coordinates of each pixel given by expressions, see [PixelExpr]. `x = "x"` and
+    /// `y = "y"` keep the image still.
+    Expr { x: PixelExpr, y: PixelExpr },
+}
+
+impl GenerateTransform for TransformPreset {
+    fn generate_transform(&self, rand: &mut Minstd, inputs: &TransformInputs) -> AnyTransform {
+        match self {
+            TransformPreset::TurnScale { scale, turn } => {
+                let (scale, turn) = (scale.sample(rand), turn.sample(rand));
+                CenterTransform::new(TurnScaleTransform::from_scale_turn_raw(scale, turn, rand))
+                    .into()
+            }
+            TransformPreset::DitherTurnScale { scale, turn } => {
+                let scale = scale.map(|s| s.sample(rand));
+                let turn = turn.map(|t| t.sample(rand));
+                DitherTurnScaleTransform::from_scale_turn_raw(scale, turn, rand).into()
+            }
+            TransformPreset::TurnVarScale { scale, turn, radial } => {
+                let turn = turn.sample(rand);
+                let scale = PresetScale { base: scale.sample(rand), radial: radial.sample(rand) };
+                CenterTransform::new(TurnVarScaleTransform::new_raw(turn, scale, rand)).into()
+            }
+            TransformPreset::Expr { x, y } => {
+                ExprTransform::new(x.clone(), y.clone(), inputs.clone()).into()
+            }
+        }
+    }
+}
+
+impl TransformPreset {
+    /// Checks all parameters and returns the name of the first invalid one with the reason
+    fn validate(&self) -> Result<(), (String, String)> {
+        let check = |name: String, range: &ParamRange, valid: (f32, f32)| {
+            range.validate(valid).map_err(|msg| (format!("transform.{name}"), msg))
+        };
+        match self {
+            TransformPreset::TurnScale { scale, turn } => {
+                check("scale".into(), scale, SCALE_LIMITS)?;
+                check("turn".into(), turn, TURN_LIMITS)
+            }
+            TransformPreset::DitherTurnScale { scale, turn } => {
+                for i in 0..2 {
+                    check(format!("scale[{i}]"), &scale[i], SCALE_LIMITS)?;
+                    check(format!("turn[{i}]"), &turn[i], TURN_LIMITS)?;
+                }
+                Ok(())
+            }
+            TransformPreset::TurnVarScale { scale, turn, radial } => {
+                check("scale".into(), scale, SCALE_LIMITS)?;
+                check("turn".into(), turn, TURN_LIMITS)?;
+                check("radial".into(), radial, (-0.01, 0.01))
+            }
+            // expressions are checked when they are parsed
+            TransformPreset::Expr { .. } => Ok(()),
+        }
+    }
+}
+
+/// Valid values of scale and turn parameters. Values outside let the image explode or spin wildly.
+const SCALE_LIMITS: (f32, f32) = (0.5, 1.5);
+const TURN_LIMITS: (f32, f32) = (-1., 1.);
+
+/// A parameter which is either fixed (`0.5`) or picked at random from a range (`[0.4, 0.6]`) each
+/// time the mode starts
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum ParamRange {
+    Fixed(f32),
+    Range([f32; 2]),
+}
+
+impl Default for ParamRange {
+    fn default() -> Self {
+        ParamRange::Fixed(0.)
+    }
+}
+
+impl ParamRange {
+    pub fn sample(&self, rand: &mut Minstd) -> f32 {
+        match *self {
+            ParamRange::Fixed(v) => v,
+            ParamRange::Range([min, max]) => min + (max - min) * rand.next_01_prom(),
+        }
+    }
+
+    fn validate(&self, (lo, hi): (f32, f32)) -> Result<(), String> {
+        let [min, max] = match *self {
+            ParamRange::Fixed(v) => [v, v],
+            ParamRange::Range(r) => r,
+        };
+        if min > max {
+            return Err(format!("minimum {min} is greater than maximum {max}"));
+        }
+        if !(lo..=hi).contains(&min) || !(lo..=hi).contains(&max) {
+            return Err(format!("values must be between {lo} and {hi}"));
+        }
+        Ok(())
+    }
+}
+
+/// Scale function of [TransformPreset::TurnVarScale]
+#[derive(Debug, Clone)]
+pub struct PresetScale {
+    base: f32,
+    radial: f32,
+}
+
+impl ScaleF for PresetScale {
+    fn scale(&self, p: Vec2f) -> f32 {
+        self.base + self.radial * p.norm()
+    }
+}
+
+impl ModePreset {
+    /// Reads and validates a preset file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            fs::read_to_string(path).map_err(|e| eyre!("Failed to read preset {path:?}: {e}"))?;
+        let preset = toml::from_str::<ModePreset>(&text)
+            .map_err(|e| eyre!("Invalid preset {path:?}: {e}"))?;
+        preset
+            .validate()
+            .map_err(|(field, msg)| eyre!("Invalid preset {path:?}: field `{field}`: {msg}"))?;
+        Ok(preset)
+    }
+
+    fn validate(&self) -> Result<(), (String, String)> {
+        if self.mode == 0 {
+            return Err(("mode".into(), "mode ids start at 1".into()));
+        }
+        for (name, freq) in EFFECT_NAMES.iter().zip(self.effect_freq.as_array()) {
+            if freq > 1000 {
+                let field = format!("effect_freq.{}", name.to_lowercase());
+                return Err((field, format!("{freq} is greater than 1000")));
+            }
+        }
+        let [min, max] = self.effect_count;
+        if min > max {
+            let msg = format!("minimum {min} is greater than maximum {max}");
+            return Err(("effect_count".into(), msg));
+        }
+        if max as usize > NUM_EFFECTS {
+            return Err(("effect_count".into(), format!("at most {NUM_EFFECTS} effects exist")));
+        }
+        // more couldn't be sampled
+        let freqs = self.effect_freq.as_array();
+        let possible = freqs.iter().filter(|&&freq| freq > 0).count();
+        if min as usize > possible {
+            let msg =
+                format!("minimum {min} is greater than the {possible} effects in `effect_freq`");
+            return Err(("effect_count".into(), msg));
+        }
+        // effects with 1000 always show and can't be removed
+        let always = freqs.iter().filter(|&&freq| freq >= 1000).count();
+        if always > max as usize {
+            let msg =
+                format!("maximum {max} is less than the {always} effects with frequency 1000");
+            return Err(("effect_count".into(), msg));
+        }
+        if !(0.5..=1.0).contains(&self.center_dwindle) {
+            return Err(("center_dwindle".into(), "must be between 0.5 and 1.0".into()));
+        }
+        self.transform.validate()
+    }
+
+    pub fn into_blueprint(self) -> ModeBlueprint {
+        ModeBlueprint {
+            effect_freq: self.effect_freq.as_array().into(),
+            solar_max: self.solar_max,
+            center_dwindle: self.center_dwindle,
+            effect_count: self.effect_count,
+            motion_dampened: self.motion_dampened,
+            tf_gen: Box::new(self.transform),
+        }
+    }
+}
+
+/// Loads all `*.toml` presets in a directory in the order of their file names
+pub fn load_presets(dir: &Path) -> Result<Vec<ModePreset>> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| eyre!("Failed to read preset directory {dir:?}: {e}"))?;
+
+    let mut paths = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect::<Vec<PathBuf>>();
+    paths.sort();
+
+    paths.iter().map(|path| ModePreset::load(path)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSFORM: &str = "[transform]\nkind = \"turn_var_scale\"\nturn = [0.01, 0.03]\n\
+                             scale = [0.9, 0.94]\nradial = 0.0003\n";
+
+    /// Loads a preset from a file and returns the error message
+    fn load_error(dir: &TempDir, name: &str, text: &str) -> String {
+        let path = dir.file(name, &format!("mode = 25\n{text}\n{TRANSFORM}"));
+        ModePreset::load(&path).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn shipped_presets_are_valid() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../presets");
+        assert!(!load_presets(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn presets_which_would_hang_sampling_are_rejected() {
+        let dir = TempDir::new("preset");
+
+        let err = load_error(&dir, "few.toml", "effect_count = [1, 2]");
+        assert!(err.contains("few.toml") && err.contains("`effect_count`"), "{err}");
+
+        let text = "effect_count = [0, 1]\n[effect_freq]\nchasers = 1000\ndots = 1000";
+        let err = load_error(&dir, "many.toml", text);
+        assert!(err.contains("many.toml") && err.contains("`effect_count`"), "{err}");
+
+        let text = "effect_count = [1, 2]\n[effect_freq]\nchasers = 1000\ndots = 1000";
+        let path = dir.file("ok.toml", &format!("mode = 25\n{text}\n{TRANSFORM}"));
+        ModePreset::load(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_fields_are_named() {
+        let dir = TempDir::new("preset-fields");
+        let err =
+            load_error(&dir, "freq.toml", "effect_count = [0, 1]\n[effect_freq]\ndots = 1001");
+        assert!(err.contains("freq.toml") && err.contains("`effect_freq.dots`"), "{err}");
+    }
+}
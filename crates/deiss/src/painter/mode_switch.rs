@@ -0,0 +1,380 @@
+use crate::painter::Globals;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How the time of the next mode switch is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwitchPolicyKind {
+    /// Switch every `min_interval` seconds like the original
+    #[default]
+    Fixed,
+
+    /// Switch after a random time between `min_interval` and `max_interval` seconds
+    Random,
+
+    /// Switch on a beat once `min_interval` seconds have passed
+    Beat,
+
+    /// Switch when the spectrum changes a lot, e.g. when a chorus starts, once `min_interval`
+    /// seconds have passed
+    Section,
+}
+
+impl SwitchPolicyKind {
+    pub const ALL: [SwitchPolicyKind; 4] = [
+        SwitchPolicyKind::Fixed,
+        SwitchPolicyKind::Random,
+        SwitchPolicyKind::Beat,
+        SwitchPolicyKind::Section,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SwitchPolicyKind::Fixed => "Fixed Interval",
+            SwitchPolicyKind::Random => "Random Interval",
+            SwitchPolicyKind::Beat => "On Beat",
+            SwitchPolicyKind::Section => "On Section Change",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SwitchSettings {
+    pub policy: SwitchPolicyKind,
+
+    /// Minimum time between switches in seconds
+    pub min_interval: f32,
+
+    /// Maximum time between switches in seconds. Policies waiting for the music switch after this
+    /// time even if nothing happened.
+    pub max_interval: f32,
+
+    /// Number of beats per bar. Beat aligned switches only happen on the first beat of a bar.
+    pub beats_per_bar: u32,
+
+    /// Spectral novelty above which a new section is detected (0.0 to 1.0+)
+    pub novelty_threshold: f32,
+}
+
+impl Default for SwitchSettings {
+    fn default() -> Self {
+        Self {
+            policy: SwitchPolicyKind::default(),
+            min_interval: 3.,
+            max_interval: 12.,
+            beats_per_bar: 1,
+            novelty_threshold: 0.5,
+        }
+    }
+}
+
+impl SwitchSettings {
+    pub fn create_policy(&self) -> Box<dyn SwitchPolicy> {
+        match self.policy {
+            SwitchPolicyKind::Fixed => Box::new(FixedInterval::new(self)),
+            SwitchPolicyKind::Random => Box::new(RandomInterval::new(self)),
+            SwitchPolicyKind::Beat => Box::new(BeatAligned::new(self)),
+            SwitchPolicyKind::Section => Box::new(SectionChange::new(self)),
+        }
+    }
+}
+
+/// Decides when to switch to the next mode. Policies only look at the clock and the audio analysis
+/// in [Globals] so that switches are deterministic with a deterministic clock.
+pub trait SwitchPolicy: Send {
+    /// Called after every mode switch
+    fn on_switch(&mut self, now: Duration, g: &mut Globals);
+
+    /// Called once per frame. Returns true if the mode should switch on this frame.
+    fn should_switch(&mut self, now: Duration, g: &Globals) -> bool;
+}
+
+pub struct FixedInterval {
+    interval: Duration,
+    next: Duration,
+}
+
+impl FixedInterval {
+    pub fn new(s: &SwitchSettings) -> Self {
+        Self { interval: secs(s.min_interval), next: Duration::ZERO }
+    }
+}
+
+impl SwitchPolicy for FixedInterval {
+    fn on_switch(&mut self, now: Duration, _: &mut Globals) {
+        self.next = now + self.interval;
+    }
+
+    fn should_switch(&mut self, now: Duration, _: &Globals) -> bool {
+        now >= self.next
+    }
+}
+
+pub struct RandomInterval {
+    min: f32,
+    max: f32,
+    next: Duration,
+}
+
+impl RandomInterval {
+    pub fn new(s: &SwitchSettings) -> Self {
+        Self { min: s.min_interval, max: s.max_interval.max(s.min_interval), next: Duration::ZERO }
+    }
+}
+
+impl SwitchPolicy for RandomInterval {
+    fn on_switch(&mut self, now: Duration, g: &mut Globals) {
+        let interval = self.min + (self.max - self.min) * g.rand.next_01_prom();
+        self.next = now + secs(interval);
+    }
+
+    fn should_switch(&mut self, now: Duration, _: &Globals) -> bool {
+        now >= self.next
+    }
+}
+
+/// Switches on the first beat of a bar. Beats come from the tempo tracker while it is locked and
+/// from [Globals::is_beat] otherwise.
+pub struct BeatAligned {
+    min_interval: Duration,
+    max_interval: Duration,
+    beats_per_bar: u32,
+    last_switch: Duration,
+    beats: u32,
+    was_beat: bool,
+    last_tempo_beat: u64,
+
+    /// True if the last call of [SwitchPolicy::should_switch] saw a beat onset
+    onset: bool,
+}
+
+impl BeatAligned {
+    pub fn new(s: &SwitchSettings) -> Self {
+        Self {
+            min_interval: secs(s.min_interval),
+            max_interval: secs(s.max_interval.max(s.min_interval)),
+            beats_per_bar: s.beats_per_bar.max(1),
+            last_switch: Duration::ZERO,
+            beats: 0,
+            was_beat: false,
+            last_tempo_beat: 0,
+            onset: false,
+        }
+    }
+}
+
+impl SwitchPolicy for BeatAligned {
+    fn on_switch(&mut self, now: Duration, _: &mut Globals) {
+        self.last_switch = now;
+        // a switch on a beat starts the first bar, switches after `max_interval` keep the bars
+        if self.onset {
+            self.beats = 0;
+        }
+    }
+
+    fn should_switch(&mut self, now: Duration, g: &Globals) -> bool {
+        let is_beat = g.is_beat();
+        let onset = if g.tempo.is_locked() {
+            g.tempo.beats() != self.last_tempo_beat
+        } else {
+            is_beat && !self.was_beat
+        };
+        self.was_beat = is_beat;
+        self.last_tempo_beat = g.tempo.beats();
+        self.onset = onset;
+        if onset {
+            self.beats += 1;
+        }
+
+        let elapsed = now.saturating_sub(self.last_switch);
+        if elapsed >= self.max_interval {
+            return true;
+        }
+        onset && elapsed >= self.min_interval && self.beats.is_multiple_of(self.beats_per_bar)
+    }
+}
+
+/// Switches when the recent spectrum differs a lot from the spectrum over the last seconds
+pub struct SectionChange {
+    min_interval: Duration,
+    max_interval: Duration,
+    threshold: f32,
+    last_switch: Duration,
+    last_update: Option<Duration>,
+
+    /// Band averages over about half a second
+    short: Vec<f32>,
+
+    /// Band averages over several seconds
+    long: Vec<f32>,
+
+    /// Number of updates of the averages since they were started
+    updates: u32,
+}
+
+/// Time constants of the spectrum averages in seconds
+const SHORT_TAU: f32 = 0.5;
+const LONG_TAU: f32 = 6.;
+
+impl SectionChange {
+    pub fn new(s: &SwitchSettings) -> Self {
+        Self {
+            min_interval: secs(s.min_interval),
+            max_interval: secs(s.max_interval.max(s.min_interval)),
+            threshold: s.novelty_threshold,
+            last_switch: Duration::ZERO,
+            last_update: None,
+            short: Vec::new(),
+            long: Vec::new(),
+            updates: 0,
+        }
+    }
+
+    /// Relative difference between the short and the long term spectrum
+    fn novelty(&self) -> f32 {
+        let total = self.long.iter().sum::<f32>();
+        if total < 1e-3 {
+            return 0.;
+        }
+        self.short.iter().zip(&self.long).map(|(s, l)| (s - l).abs()).sum::<f32>() / total
+    }
+}
+
+impl SwitchPolicy for SectionChange {
+    fn on_switch(&mut self, now: Duration, _: &mut Globals) {
+        self.last_switch = now;
+        // the current section becomes the reference
+        self.long.clone_from(&self.short);
+    }
+
+    fn should_switch(&mut self, now: Duration, g: &Globals) -> bool {
+        let bands = g.spectrum.smoothed();
+        // start averaging with the first sound, silence is no reference
+        if self.short.len() != bands.len() || self.long.iter().sum::<f32>() < 1e-3 {
+            self.short = bands.to_vec();
+            self.long = bands.to_vec();
+            self.updates = 0;
+        }
+
+        let dt = self.last_update.map_or(0., |t| now.saturating_sub(t).as_secs_f32());
+        self.last_update = Some(now);
+        let short_rate = 1. - (-dt / SHORT_TAU).exp();
+        // plain mean until the long average covers enough time
+        self.updates += 1;
+        let long_rate = (1. - (-dt / LONG_TAU).exp()).max(1. / self.updates as f32);
+        for ((s, l), &v) in self.short.iter_mut().zip(self.long.iter_mut()).zip(bands) {
+            *s += (v - *s) * short_rate;
+            *l += (v - *l) * long_rate;
+        }
+
+        let elapsed = now.saturating_sub(self.last_switch);
+        if elapsed >= self.max_interval {
+            return true;
+        }
+        elapsed >= self.min_interval && self.novelty() > self.threshold
+    }
+}
+
+fn secs(s: f32) -> Duration {
+    Duration::from_secs_f32(s.max(0.))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::painter::{ANALYSIS_SAMPLE_RATE, Clock, SpectrumSettings};
+
+    const FPS: f32 = 30.;
+
+    /// Runs a policy on a fixed clock for the given number of frames and returns the frames on
+    /// which it switched. `update` fakes the audio analysis of each frame.
+    fn run(s: &SwitchSettings, frames: u64, mut update: impl FnMut(u64, &mut Globals)) -> Vec<u64> {
+        let mut policy = s.create_policy();
+        let mut g = Globals::default();
+        let mut clock = Clock::fixed(FPS);
+        policy.on_switch(clock.now(), &mut g);
+
+        let mut switches = vec![0];
+        for frame in 1..frames {
+            clock.tick();
+            update(frame, &mut g);
+            if policy.should_switch(clock.now(), &g) {
+                policy.on_switch(clock.now(), &mut g);
+                switches.push(frame);
+            }
+        }
+        switches
+    }
+
+    fn settings(policy: SwitchPolicyKind, min_interval: f32, max_interval: f32) -> SwitchSettings {
+        SwitchSettings { policy, min_interval, max_interval, ..Default::default() }
+    }
+
+    /// Volume analysis with a beat on every frame `n * period + phase`
+    fn beats(period: u64, phase: u64) -> impl FnMut(u64, &mut Globals) {
+        move |frame, g| {
+            if frame == 1 {
+                for _ in 0..g.vol_narrow.len() {
+                    g.vol_narrow.push(10.);
+                }
+            }
+            g.avg_vol_narrow = if frame % period == phase { 20. } else { 10. };
+        }
+    }
+
+    /// Spectrum of a sine which changes its frequency at frame `change`
+    fn section(change: u64) -> impl FnMut(u64, &mut Globals) {
+        let s = SpectrumSettings::default();
+        let frames = (ANALYSIS_SAMPLE_RATE as f32 / FPS) as usize;
+        move |frame, g| {
+            let freq = if frame < change { 200. } else { 3000. };
+            let stereo = (0..frames)
+                .flat_map(|i| {
+                    let t = (frame as usize * frames + i) as f32 / ANALYSIS_SAMPLE_RATE as f32;
+                    let v = 0.5 * (core::f32::consts::TAU * freq * t).sin();
+                    [v, v]
+                })
+                .collect::<Vec<_>>();
+            g.spectrum.analyze(&stereo, &s);
+        }
+    }
+
+    #[test]
+    fn fixed_interval_switches_every_min_interval() {
+        let s = settings(SwitchPolicyKind::Fixed, 3., 12.);
+        assert_eq!(run(&s, 300, beats(15, 5)), [0, 90, 180, 270]);
+    }
+
+    #[test]
+    fn random_interval_stays_within_the_limits() {
+        let s = settings(SwitchPolicyKind::Random, 2., 4.);
+        let switches = run(&s, 2000, |_, _| {});
+        let gaps = switches.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+        assert!(gaps.iter().all(|gap| (60..=120).contains(gap)), "{gaps:?}");
+        assert!(gaps.iter().any(|&gap| gap != gaps[0]), "{gaps:?}");
+        assert_eq!(run(&s, 2000, |_, _| {}), switches);
+    }
+
+    #[test]
+    fn beat_aligned_switches_on_the_first_beat_of_a_bar() {
+        let s = SwitchSettings { beats_per_bar: 4, ..settings(SwitchPolicyKind::Beat, 1., 12.) };
+        // beats on frames 5, 20, 35 and 50 make the first bar
+        assert_eq!(run(&s, 200, beats(15, 5)), [0, 50, 110, 170]);
+    }
+
+    #[test]
+    fn beat_aligned_keeps_the_bars_after_max_interval() {
+        let s = SwitchSettings { beats_per_bar: 4, ..settings(SwitchPolicyKind::Beat, 0.25, 4.) };
+        // the third beat is on frame 90, the fourth one after the forced switch on frame 130
+        assert_eq!(run(&s, 200, beats(40, 10)), [0, 120, 130]);
+    }
+
+    #[test]
+    fn section_change_switches_when_the_spectrum_changes() {
+        let s = settings(SwitchPolicyKind::Section, 1., 20.);
+        let switches = run(&s, 300, section(200));
+        assert!(switches.len() >= 2 && (200..230).contains(&switches[1]), "{switches:?}");
+    }
+}
@@ -5,56 +5,153 @@ use crate::{
     renderer::CrtShaderSettings,
     utils::*,
 };
-use std::{f32, mem};
+use eyre::Result;
+use std::{
+    f32, mem,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 pub struct Painter {
     pub(crate) settings: Settings,
     pub(crate) library: ModeBlueprintLibrary,
     pub(crate) globals: Globals,
+    pub(crate) bookmarks: Vec<Bookmark>,
     img: RgbaImage,
     next: RgbaImage,
     fx_hub: WarpMapHub,
-    fx: Option<(WarpSpec, WarpMap)>,
+    fx: Option<(WarpSpec, BakedWarp)>,
+    transition: Option<Transition>,
+    dynamic: DynamicWarp,
     needs_init: bool,
     wave: Wave,
+    /// Clock time at which audio was received last
+    last_audio: Option<Duration>,
 }
 
 impl Painter {
-    pub fn new(shape: Shape2) -> Self {
-        const YCUT: u32 = 90;
-
-        let mut globals = Globals::default();
+    /// Creates a painter using the given clock. If a seed is given the random number generator is
+    /// seeded with it, otherwise the default seed is used.
+    pub fn new(shape: Shape2, clock: Clock, seed: Option<u64>) -> Self {
+        let mut globals = Globals {
+            rand: seed.map(Minstd::from_seed).unwrap_or_default(),
+            clock,
+            ..Default::default()
+        };
         globals.chaser_offset = globals.rand.next_idx(40_000) as f32;
         globals.fps_at_last_mode_switch = 30.;
         globals.time_scale = 1.;
 
         let (fxh, fxw) = shape.into();
 
-        let settings = Settings {
-            volscale: 0.2,
+        let mut settings = Settings {
+            volscale: DEFAULT_VOLSCALE,
             enable_map_dampening: false,
             fxw,
             fxh,
-            y_roi: YRoi { min: YCUT, max: fxh - YCUT },
+            y_roi: YRoi::letterbox(shape),
             gf: generate_gf(&mut globals.rand),
-            mode_prefs: ModePrefs::new(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]),
+            mode_prefs: ModePrefs::default(),
             waveform_prefs: WaveformPrefs::default(),
+            spectrum: SpectrumSettings::default(),
+            transition: TransitionSettings::default(),
+            switch: SwitchSettings::default(),
+            dynamic: DynamicWarpSettings::default(),
+            bookmark_share: 0.,
             crt_shader_settings: CrtShaderSettings::default(),
         };
 
         let library = ModeBlueprintLibrary::new(&mut globals);
+        settings.mode_prefs = ModePrefs::new(&library.mode_ids());
 
         Self {
             img: RgbaImage::black(shape),
             next: RgbaImage::black(shape),
             fx_hub: WarpMapHub::new(),
             fx: None,
+            transition: None,
+            dynamic: DynamicWarp::default(),
             needs_init: true,
             wave: Wave::new(&globals),
+            last_audio: None,
             settings,
             library,
             globals,
+            bookmarks: Vec::new(),
+        }
+    }
+
+    /// Changes the resolution of the paint buffer. The image is scaled to the new shape and the
+    /// current mode is baked again and shown as soon as it is ready.
+    pub fn resize(&mut self, shape: Shape2) {
+        if shape == self.settings.shape() {
+            return;
+        }
+
+        let (fxh, fxw) = shape.into();
+        self.settings.fxw = fxw;
+        self.settings.fxh = fxh;
+        self.settings.y_roi = YRoi::letterbox(shape);
+
+        self.img = self.img.resized(shape);
+        self.next = RgbaImage::black(shape);
+
+        // maps of the old resolution can't be applied anymore
+        let mode = self.mode();
+        self.fx = None;
+        self.transition = None;
+        self.dynamic.reset();
+        self.fx_hub.restart(mode);
+    }
+
+    /// Adds the mode presets of the given directories. All modes start with the default weight.
+    pub fn load_presets(&mut self, dirs: &[PathBuf]) -> Result<()> {
+        for dir in dirs {
+            self.library.add_presets(dir)?;
+        }
+        self.settings.mode_prefs = ModePrefs::new(&self.library.mode_ids());
+        Ok(())
+    }
+
+    /// Adds the bookmarks of a favorites file
+    pub fn load_bookmarks(&mut self, path: &Path) -> Result<()> {
+        self.bookmarks.extend(load_bookmarks(path)?);
+        Ok(())
+    }
+
+    pub fn bookmarks(&self) -> &[Bookmark] {
+        &self.bookmarks
+    }
+
+    /// Bookmarks the look which is currently shown. Returns false if no mode is shown yet.
+    pub fn add_bookmark(&mut self, name: Option<String>) -> bool {
+        let Some((spec, _)) = &self.fx else {
+            return false;
+        };
+        let name =
+            name.unwrap_or_else(|| format!("Mode {} #{}", spec.mode.0, self.bookmarks.len() + 1));
+        self.bookmarks.push(Bookmark::new(name, spec, &self.settings));
+        true
+    }
+
+    pub fn remove_bookmark(&mut self, index: usize) {
+        if index < self.bookmarks.len() {
+            self.bookmarks.remove(index);
+        }
+    }
+
+    /// Shows a bookmark as soon as its map is baked. A locked mode stays locked to the mode of the
+    /// bookmark.
+    pub fn recall_bookmark(&mut self, index: usize) -> Result<()> {
+        let Some(bookmark) = self.bookmarks.get(index) else {
+            return Ok(());
+        };
+        let spec = bookmark.to_spec(&self.settings, &self.library, &self.globals)?;
+        if self.settings.mode_prefs.priority().is_some() {
+            self.settings.mode_prefs.set_priority(Some(spec.mode));
         }
+        self.fx_hub.show(spec);
+        Ok(())
     }
 
     pub fn image(&self) -> &RgbaImage {
@@ -65,20 +162,79 @@ impl Painter {
         &self.settings
     }
 
+    /// The mode which is currently shown
+    pub fn mode(&self) -> Option<ModeId> {
+        self.fx.as_ref().map(|(spec, _)| spec.mode)
+    }
+
+    /// The waveform which is currently shown
+    pub fn waveform(&self) -> Option<WaveformId> {
+        self.fx.as_ref().map(|(spec, _)| spec.waveform)
+    }
+
+    /// Switches to the mode `step` places after the current one in the list of modes. A locked mode
+    /// stays locked to the new mode.
+    pub fn step_mode(&mut self, step: i32) {
+        let modes = self.settings.mode_prefs.weights().iter().map(|&(m, _)| m).collect::<Vec<_>>();
+        let Some(current) = self.mode().and_then(|m| modes.iter().position(|&x| x == m)) else {
+            return;
+        };
+
+        let mode = modes[(current as i32 + step).rem_euclid(modes.len() as i32) as usize];
+        if self.settings.mode_prefs.priority().is_some() {
+            self.settings.mode_prefs.set_priority(Some(mode));
+        }
+        self.fx_hub.switch_to(mode);
+    }
+
+    /// Locks the current mode so that it is always picked, or unlocks a locked mode
+    pub fn toggle_mode_lock(&mut self) {
+        let priority = match self.settings.mode_prefs.priority() {
+            Some(_) => None,
+            None => self.mode(),
+        };
+        self.settings.mode_prefs.set_priority(priority);
+    }
+
     pub fn on_render(&mut self) {
         self.globals.frame += 1;
         self.globals.floatframe += 1.6 * (47.0 / self.globals.fps_at_last_mode_switch).min(1.);
 
+        self.globals.clock.tick();
         self.globals.fps.step();
 
-        self.fx_hub.step(&self.settings, &self.library, &mut self.globals).ok();
+        // forget stale audio when nothing arrives anymore, e.g. between tracks
+        let now = self.globals.clock.now();
+        if let Some(last) = self.last_audio
+            && now.saturating_sub(last).as_secs_f32() > STALE_SECS
+        {
+            self.last_audio = None;
+            self.globals.reset_analysis();
+        }
+        if self.last_audio.is_none() {
+            self.globals.sound_empty = true;
+        }
+
+        self.fx_hub.step(&self.settings, &self.library, &self.bookmarks, &mut self.globals).ok();
         if let Some(fx) = self.fx_hub.fetch() {
             log::info!("New mode: {:?} W{:?} {:?}", fx.0.mode, fx.0.waveform, fx.0.effects);
-            self.fx = Some(fx);
+            if let Some(gf) = fx.0.gf {
+                self.settings.gf = gf;
+            }
+            let previous = self.fx.replace(fx);
+            // cross-fade from the map which was shown last
+            let last_map = self.dynamic.take_map();
+            self.dynamic.reset();
+            self.transition = match previous {
+                Some((_, baked)) if self.settings.transition.frames > 0 => {
+                    Some(Transition::new(last_map.unwrap_or(baked.map)))
+                }
+                _ => None,
+            };
             self.needs_init = true;
         }
 
-        let Some((spec, fx)) = self.fx.as_ref() else {
+        let Some((spec, baked)) = self.fx.as_mut() else {
             return;
         };
 
@@ -127,6 +283,11 @@ impl Painter {
                 .render(&mut self.img, &mut self.globals.rand);
         }
 
+        if spec.effects[EffectKind::Spectral] {
+            fx::Spectral::new(spec.center, &self.settings, &self.globals)
+                .render(&mut self.img, &mut self.globals.rand);
+        }
+
         {
             let center_dwindle = self.library[spec.mode].center_dwindle;
             if center_dwindle < 0.999 {
@@ -136,9 +297,29 @@ impl Painter {
             }
         }
 
-        process_map(&self.settings, fx.as_slice(), &self.img, &mut self.next);
+        let fx =
+            self.dynamic.step(&self.settings, spec, baked, &self.globals).unwrap_or(&baked.map);
+        let weight = self.transition.as_mut().and_then(|t| t.step(&self.settings.transition));
+        match (&self.transition, weight) {
+            (Some(transition), Some(weight)) => process_map_blend(
+                &self.settings,
+                transition.from.as_slice(),
+                fx.as_slice(),
+                weight,
+                &self.img,
+                &mut self.next,
+            ),
+            _ => {
+                self.transition = None;
+                process_map(&self.settings, fx.as_slice(), &self.img, &mut self.next);
+            }
+        }
         mem::swap(&mut self.img, &mut self.next);
 
+        if self.globals.sound_empty {
+            fx::Fade::new(EMPTY_FADE, &self.settings).render(&mut self.img, &mut self.globals.rand);
+        }
+
         // render dots on beats
         fx::Nuclide::new_beat_dots(spec.center, &self.settings, &mut self.globals)
             .render(&mut self.img, &mut self.globals.rand);
@@ -155,24 +336,61 @@ impl Painter {
 }
 
 impl AudioListener for Painter {
-    fn buffer_size(&self) -> usize {
-        ((self.settings.fxw * 2) as usize).max((WAVE_5_SIZE + WAVE_5_BLEND_RANGE) * 2 + 20)
+    fn buffer_size(&self, sample_rate: u32) -> usize {
+        // waveforms interpolate the samples so that they show the same audio at every resolution
+        let frames = (REFERENCE_WIDTH as usize).max(WAVE_5_SIZE + WAVE_5_BLEND_RANGE + 10);
+        // enough frames to have `frames` frames after resampling to the analysis rate
+        (frames as u64 * sample_rate as u64).div_ceil(ANALYSIS_SAMPLE_RATE as u64) as usize + 1
     }
 
     fn on_samples(&mut self, wave: &AudioSamples) {
+        self.last_audio = Some(self.globals.clock.now());
         process_wave_data(wave, &self.settings, &mut self.globals);
     }
+
+    fn on_seek(&mut self) {
+        self.globals.reset_analysis();
+    }
 }
 
+/// Audio is resampled to this rate before analysis. The original was tuned for 44.1 kHz and
+/// all filters and frequencies below assume it.
+pub const ANALYSIS_SAMPLE_RATE: u32 = 44_100;
+
+/// Peaks below this level (about -50 dBFS in the units of [volume]) count as silence
+const SILENCE_LEVEL: f32 = 0.8;
+
+/// Seconds of silence after which the sound counts as empty
+const SILENCE_SECS: f32 = 0.5;
+
+/// Seconds without audio after which the last received audio counts as stale
+const STALE_SECS: f32 = 0.3;
+
+/// Time constant of the decay of [Globals::kick] in seconds
+const KICK_DECAY_SECS: f32 = 0.12;
+
+/// Per frame decay of the image while the sound is empty
+const EMPTY_FADE: f32 = 0.94;
+
+/// The original operated on signed 16 bit samples. Thresholds and scale factors below are derived
+/// from the original constants by dividing by this factor.
+const SAMPLE_SCALE: f32 = 32768.;
+
 fn process_wave_data(wave: &AudioSamples, s: &Settings, g: &mut Globals) {
-    let mut buf = wave.iter().map(|&v| v as f32).collect::<Vec<_>>();
+    let mut buf = wave.resample(ANALYSIS_SAMPLE_RATE).to_stereo();
 
-    level_trigger(&mut buf, s, g);
+    g.spectrum.analyze(&buf, &s.spectrum);
+    g.tempo.update(g.spectrum.onset(), wave.frames() as f32 / wave.sample_rate() as f32);
+
+    level_trigger(&mut buf, g);
 
     let vol = volume(&buf);
     g.vol.push(vol);
 
-    let peaks = 0.; // TODO seems to be always 0 in the source
+    let peaks = peak(&buf);
+    let dt = wave.frames() as f32 / wave.sample_rate() as f32;
+    g.silence = if peaks < SILENCE_LEVEL { g.silence + dt } else { 0. };
+    g.sound_empty = g.silence >= SILENCE_SECS;
 
     g.avg_vol_narrow =
         blend(adjust_rate_to_fps(0.30, 30., g.fps_at_last_mode_switch), (g.avg_vol_narrow, vol));
@@ -185,20 +403,18 @@ fn process_wave_data(wave: &AudioSamples, s: &Settings, g: &mut Globals) {
 
     g.vol_narrow.push(g.avg_vol_narrow);
 
+    let is_beat = g.is_beat();
+    g.kick = if is_beat && !g.was_beat { 1. } else { g.kick * (-dt / KICK_DECAY_SECS).exp() };
+    g.was_beat = is_beat;
+
     low_pass_filter_inplace(&mut buf);
 
-    let fdiv = 1.0 / (64.0 * (640.0 / s.fxw as f32));
-    let billy = s.volscale * fdiv; // * 4.;
+    let fdiv = 1.0 / (64.0 / s.res_scale());
+    let billy = s.volscale * fdiv * SAMPLE_SCALE; // * 4.;
     scale_inplace(&mut buf, billy);
 
-    // centroid for L, R channels
-    let centroid = channel_centroid(&buf);
-
-    // translate wave so center it at zero
-    sub_inplace(&mut buf, centroid);
-
-    // compute power using fourier
-    let mut net_power_change = g.fourier.fourier(&buf);
+    // spectral flux in the units of the original 256 sample DFT of the scaled wave
+    let mut net_power_change = g.spectrum.flux() * 128. * billy;
     net_power_change /= (g.volume_sum as f32) / (g.frame + 1) as f32;
     net_power_change *= 0.01;
 
@@ -228,14 +444,14 @@ fn blend(p: f32, (a, b): (f32, f32)) -> f32 {
     p * a + (1.0 - p) * b
 }
 
-fn level_trigger(buf: &mut [f32], s: &Settings, g: &mut Globals) {
-    let fxw_div_2 = s.fxw as usize / 2;
+fn level_trigger(buf: &mut [f32], g: &mut Globals) {
+    let fxw_div_2 = REFERENCE_WIDTH as usize / 2;
     let mut trigger = None;
     for i in (8..fxw_div_2).step_by(2) {
         let v_old = buf[i + fxw_div_2 - 8];
         let v = buf[i + fxw_div_2];
 
-        let height_match = (v - g.last_frame_v).abs() <= 256.;
+        let height_match = (v - g.last_frame_v).abs() <= 256. / SAMPLE_SCALE;
         let slope_match = g.last_frame_slope * (v - v_old) >= 0.;
 
         if height_match && slope_match {
@@ -258,6 +474,12 @@ fn level_trigger(buf: &mut [f32], s: &Settings, g: &mut Globals) {
     }
 }
 
+/// Largest absolute sample in the units of [volume], i.e. half the peak-to-peak range of a
+/// symmetric wave
+fn peak(buf: &[f32]) -> f32 {
+    buf.iter().fold(0., |acc: f32, v| acc.max(v.abs())) * (SAMPLE_SCALE / 128.0)
+}
+
 fn volume(buf: &[f32]) -> f32 {
     let mut low = buf[0];
     let mut high = low;
@@ -265,7 +487,7 @@ fn volume(buf: &[f32]) -> f32 {
         low = low.min(v);
         high = high.max(v);
     }
-    (high - low) / 256.0
+    (high - low) * (SAMPLE_SCALE / 256.0)
 }
 
 // low pass filter for bass hits and scale
@@ -281,57 +503,52 @@ fn scale_inplace(buf: &mut [f32], scale: f32) {
     }
 }
 
-fn channel_centroid(buf: &[f32]) -> [f32; 2] {
-    let mut centroid = [0.; 2];
-    for i in (0..buf.len()).step_by(8) {
-        centroid[0] += buf[i];
-        centroid[1] += buf[i + 1];
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stereo test signal: a tone with a kick every half second
+    fn test_samples(chunk: usize, len: usize, sample_rate: u32) -> Vec<f32> {
+        let t0 = chunk * len;
+        (t0..t0 + len)
+            .flat_map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let kick = (-(t % 0.5) * 30.).exp() * (t * 60. * f32::consts::TAU).sin();
+                let tone = 0.3 * (t * 440. * f32::consts::TAU).sin();
+                [kick * 0.6 + tone, kick * 0.6 - tone]
+            })
+            .collect()
     }
-    let z = 1. / (buf.len() / 8) as f32;
-    [centroid[0] * z, centroid[1] * z]
-}
 
-fn sub_inplace(buf: &mut [f32], centroid: [f32; 2]) {
-    for i in (0..buf.len()).step_by(2) {
-        buf[i] -= centroid[0];
-        buf[i + 1] -= centroid[1];
+    fn render(seed: u64, frames: usize) -> Vec<Vec<u8>> {
+        let sample_rate = 44_100;
+        let mut painter = Painter::new((240, 320).into(), Clock::fixed(30.), Some(seed));
+        let len = painter.buffer_size(sample_rate);
+        (0..frames)
+            .map(|frame| {
+                painter.on_samples(&AudioSamples::new(
+                    test_samples(frame, len, sample_rate),
+                    sample_rate,
+                    2,
+                ));
+                painter.on_render();
+                painter.image().as_bytes().to_vec()
+            })
+            .collect()
     }
-}
-
-#[derive(Default, Debug)]
-pub struct RunningFourier {
-    power: [f32; Self::FOURIER_DETAIL],
-    power_smoothed: [f32; Self::FOURIER_DETAIL],
-}
-
-impl RunningFourier {
-    const FOURIER_DETAIL: usize = 24;
-}
 
-impl RunningFourier {
-    fn fourier(&mut self, buf: &[f32]) -> f32 {
-        let mut net_power_change = 0.;
-
-        for n in 1..Self::FOURIER_DETAIL {
-            let w = f32::consts::TAU
-                * (20. * 2.0_f32.powf((n as f32) / (Self::FOURIER_DETAIL as f32) * 10.) / 44_100.);
-
-            let mut a = 0.;
-            let mut b = 0.;
-            for i in 0..256 {
-                let (sin_th, cos_th) = (w * i as f32).sin_cos();
-                let v = buf[2 * i];
-                a += v * sin_th;
-                b += v * cos_th;
-            }
-
-            let old_power = self.power[n];
-            self.power[n] = a.hypot(b);
-            net_power_change += (old_power - self.power[n]).abs();
-
-            self.power_smoothed[n] = 0.94 * self.power_smoothed[n] + 0.06 * self.power[n];
+    #[test]
+    fn fixed_clock_renders_identical_frames() {
+        let a = render(7, 150);
+        let b = render(7, 150);
+        assert!(a.last().unwrap().iter().any(|&v| v != 0), "nothing was painted");
+        for (frame, (a, b)) in a.iter().zip(&b).enumerate() {
+            assert!(a == b, "frame {frame} differs");
         }
+    }
 
-        net_power_change
+    #[test]
+    fn seed_changes_frames() {
+        assert_ne!(render(7, 60).last(), render(8, 60).last());
     }
 }
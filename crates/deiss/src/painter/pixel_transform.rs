@@ -1,7 +1,49 @@
-use crate::utils::*;
+use crate::{
+    painter::{Globals, REFERENCE_HEIGHT, REFERENCE_WIDTH},
+    utils::*,
+};
 
 pub trait GeneralPixelTransform {
     fn transform(&self, point: Vec2f, center: Vec2f, shape: Vec2f) -> Vec2f;
+
+    /// True if the transform depends on [TransformInputs] and is re-baked while its mode is shown
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+
+    /// Updates the time and audio levels before a dynamic transform is re-baked
+    fn set_inputs(&mut self, _inputs: &TransformInputs) {}
+}
+
+/// Time and audio levels which transforms can react to
+#[derive(Debug, Clone, Default)]
+pub struct TransformInputs {
+    /// Seconds since the start
+    pub time: f32,
+
+    /// Normalized spectrum levels (0.0 to 1.0)
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
+
+    /// Position within the current beat (0.0 to 1.0), see [crate::painter::Tempo::phase]
+    pub phase: f32,
+
+    /// 1.0 at the start of a beat decaying quickly to 0.0, see [Globals::kick]
+    pub kick: f32,
+}
+
+impl TransformInputs {
+    pub fn new(g: &Globals) -> Self {
+        Self {
+            time: g.clock.now().as_secs_f32(),
+            bass: g.spectrum.bass(),
+            mid: g.spectrum.mid(),
+            treble: g.spectrum.treble(),
+            phase: g.tempo.phase(),
+            kick: g.kick,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -16,12 +58,21 @@ impl<T> CenterTransform<T> {
 }
 
 impl<T: PixelTransform> GeneralPixelTransform for CenterTransform<T> {
-    fn transform(&self, point: Vec2f, center: Vec2f, _: Vec2f) -> Vec2f {
-        self.inner.transform(point - center) + center
+    fn transform(&self, point: Vec2f, center: Vec2f, shape: Vec2f) -> Vec2f {
+        let k = res_scale(shape);
+        self.inner.transform((point - center) * (1. / k)) * k + center
     }
 }
 
-/// Pixel coordinate transformation mainly used for motion fields
+/// Size of a reference pixel in pixels for an image of the given shape, see [REFERENCE_WIDTH].
+/// The reference image fits into the image, so other aspect ratios show more of the scene along
+/// the longer side instead of stretching it.
+pub fn res_scale(shape: Vec2f) -> f32 {
+    (shape.x / REFERENCE_WIDTH as f32).min(shape.y / REFERENCE_HEIGHT as f32)
+}
+
+/// Pixel coordinate transformation mainly used for motion fields. Points are relative to the
+/// center and in pixels of the reference resolution, see [REFERENCE_WIDTH].
 pub trait PixelTransform {
     fn transform(&self, p: Vec2f) -> Vec2f;
 }
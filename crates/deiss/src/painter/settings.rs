@@ -1,4 +1,17 @@
 use crate::{painter::*, renderer::CrtShaderSettings, utils::*};
+use serde::{Deserialize, Serialize};
+
+/// Resolution the modes, effects and waveforms were designed for. Sizes and distances are given
+/// in pixels of this resolution and scaled by [Settings::res_scale].
+pub const REFERENCE_WIDTH: u32 = 640;
+pub const REFERENCE_HEIGHT: u32 = 480;
+
+/// Rows which are painted at the reference resolution. The original cut 90 rows off at the top
+/// and the bottom.
+pub const REFERENCE_Y_ROI: YRoi = YRoi { min: 90, max: REFERENCE_HEIGHT - 90 };
+
+/// Initial [Settings::volscale]
+pub const DEFAULT_VOLSCALE: f32 = 0.2;
 
 #[derive(Debug, Clone)]
 pub struct Settings {
@@ -10,6 +23,14 @@ pub struct Settings {
     pub gf: [f32; 6],
     pub mode_prefs: ModePrefs,
     pub waveform_prefs: WaveformPrefs,
+    pub spectrum: SpectrumSettings,
+    pub transition: TransitionSettings,
+    pub switch: SwitchSettings,
+    pub dynamic: DynamicWarpSettings,
+
+    /// Probability that a mode switch shows a bookmark instead of a new random look (0.0 to 1.0)
+    pub bookmark_share: f32,
+
     pub crt_shader_settings: CrtShaderSettings,
 }
 
@@ -17,6 +38,21 @@ impl Settings {
     pub fn shape(&self) -> Shape2 {
         (self.fxh, self.fxw).into()
     }
+
+    /// Size of a reference pixel in pixels, see [res_scale]
+    pub fn res_scale(&self) -> f32 {
+        res_scale(self.shape().size())
+    }
+
+    /// Side length in pixels of a dot which is one pixel wide at the reference resolution
+    pub fn dot_size(&self) -> u32 {
+        dot_size(self.shape())
+    }
+}
+
+/// [Settings::dot_size] for an image of the given shape
+pub fn dot_size(shape: Shape2) -> u32 {
+    (res_scale(shape.size()).round() as u32).max(1)
 }
 
 pub fn generate_gf(rand: &mut Minstd) -> [f32; 6] {
@@ -30,23 +66,41 @@ pub struct YRoi {
 }
 
 impl YRoi {
+    /// Rows which are painted for the given shape. Rows are only cut off if the image is taller
+    /// than the band of [REFERENCE_Y_ROI], and at most the same fraction as at the reference
+    /// resolution, so wide images are painted completely.
+    pub fn letterbox(shape: Shape2) -> Self {
+        let (rows, cols) = shape.into();
+        let band = cols * REFERENCE_Y_ROI.rows() / REFERENCE_WIDTH;
+        let cut =
+            (rows.saturating_sub(band) / 2).min(REFERENCE_Y_ROI.min * rows / REFERENCE_HEIGHT);
+        Self { min: cut, max: rows - cut }
+    }
+
     pub fn contains(&self, y: u32) -> bool {
         self.min <= y && y <= self.max
     }
+
+    /// Number of rows between min and max
+    pub fn rows(&self) -> u32 {
+        self.max - self.min
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ModePrefs {
     /// If set always pick this mode
     priority: Option<ModeId>,
 
     /// Weights to select modes (also indicates valid modes). Weights are 0 to 5 stars.
+    #[serde(with = "mode_weights")]
     weights: Vec<(ModeId, u32)>,
 }
 
 impl ModePrefs {
-    pub fn new(modes: &[u32]) -> Self {
-        Self { priority: None, weights: modes.into_iter().map(|&i| (ModeId(i), 3)).collect() }
+    pub fn new(modes: &[ModeId]) -> Self {
+        Self { priority: None, weights: modes.iter().map(|&m| (m, 3)).collect() }
     }
 }
 
@@ -87,6 +141,49 @@ impl ModePrefs {
     pub fn set_priority(&mut self, priority: Option<ModeId>) {
         self.priority = priority;
     }
+
+    /// Sets the weight of a mode, adding the mode if it is missing
+    pub fn set_weight(&mut self, mode: ModeId, weight: u32) {
+        match self.weights.binary_search_by_key(&mode, |&(m, _)| m) {
+            Ok(i) => self.weights[i].1 = weight,
+            Err(i) => self.weights.insert(i, (mode, weight)),
+        }
+    }
+
+    /// Takes the weights and the priority of `saved` for the modes which are known here. Modes
+    /// which are only in `saved`, e.g. of presets which aren't loaded, are ignored.
+    pub fn restore(&mut self, saved: &ModePrefs) {
+        for (mode, weight) in self.weights.iter_mut() {
+            if let Some(&(_, w)) = saved.weights.iter().find(|(m, _)| m == mode) {
+                *weight = w.min(5);
+            }
+        }
+        self.priority = saved.priority.filter(|p| self.weights.iter().any(|(m, _)| m == p));
+    }
+}
+
+/// Stores mode weights as a table from mode id to stars because keys of TOML tables must be
+/// strings
+mod mode_weights {
+    use crate::painter::ModeId;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+    use std::collections::HashMap;
+
+    pub fn serialize<S: Serializer>(weights: &[(ModeId, u32)], ser: S) -> Result<S::Ok, S::Error> {
+        ser.collect_map(weights.iter().map(|(m, w)| (m.0.to_string(), w)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(de: D) -> Result<Vec<(ModeId, u32)>, D::Error> {
+        let mut weights = HashMap::<String, u32>::deserialize(de)?
+            .into_iter()
+            .map(|(id, w)| match id.parse() {
+                Ok(id) => Ok((ModeId(id), w)),
+                Err(_) => Err(D::Error::custom(format!("invalid mode id '{id}'"))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        weights.sort();
+        Ok(weights)
+    }
 }
 
 pub fn color_gen(gf: [f32; 6], f: f32, t: f32, c: [f32; 2], ph: [f32; 6]) -> [f32; 6] {
@@ -100,10 +197,12 @@ pub fn color_gen(gf: [f32; 6], f: f32, t: f32, c: [f32; 2], ph: [f32; 6]) -> [f3
     ]
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct WaveformId(pub u32);
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct WaveformPrefs {
     /// If set always pick this waveform
     priority: Option<WaveformId>,
@@ -139,3 +238,33 @@ impl WaveformPrefs {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn letterbox(rows: u32, cols: u32) -> (u32, u32) {
+        let roi = YRoi::letterbox((rows, cols).into());
+        (roi.min, roi.max)
+    }
+
+    #[test]
+    fn letterbox_cuts_like_the_reference() {
+        assert_eq!(letterbox(REFERENCE_HEIGHT, REFERENCE_WIDTH), (90, 390));
+        assert_eq!(letterbox(960, 1280), (180, 780));
+    }
+
+    #[test]
+    fn letterbox_keeps_wide_images() {
+        assert_eq!(letterbox(1080, 1920), (90, 990));
+        assert_eq!(letterbox(1080, 2560), (0, 1080));
+    }
+
+    #[test]
+    fn letterbox_cuts_tall_images_at_most_like_the_reference() {
+        assert_eq!(letterbox(1920, 1080), (360, 1560));
+        let (min, max) = letterbox(1920, 1080);
+        assert_eq!(min * REFERENCE_HEIGHT, REFERENCE_Y_ROI.min * 1920);
+        assert_eq!(min, 1920 - max);
+    }
+}
@@ -0,0 +1,306 @@
+use crate::painter::ANALYSIS_SAMPLE_RATE;
+use core::fmt;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Number of samples analyzed by one FFT (about 23 ms at the analysis rate)
+pub const FFT_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpectrumSettings {
+    /// Number of log-spaced bands
+    pub bands: usize,
+
+    /// Lower edge of the lowest band in Hz
+    pub min_freq: f32,
+
+    /// Upper edge of the highest band in Hz
+    pub max_freq: f32,
+
+    /// Weight of the previous value when smoothing bands (0.0 = no smoothing)
+    pub smoothing: f32,
+
+    /// Factor by which held peaks fall per analyzed buffer
+    pub peak_decay: f32,
+
+    /// Factor by which the normalization level falls per analyzed buffer. Values close to 1.0 adapt
+    /// slowly to quieter passages.
+    pub normalization_decay: f32,
+}
+
+impl Default for SpectrumSettings {
+    fn default() -> Self {
+        Self {
+            bands: 24,
+            min_freq: 20.,
+            max_freq: 20_000.,
+            smoothing: 0.6,
+            peak_decay: 0.97,
+            normalization_decay: 0.998,
+        }
+    }
+}
+
+/// Windowed FFT analyzer which splits the spectrum into log-spaced bands.
+///
+/// Band values are magnitudes where a sine wave of amplitude `a` has a magnitude of about `a`.
+pub struct Spectrum {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+
+    /// The last [FFT_SIZE] mono samples, oldest first. Buffers are usually shorter than
+    /// [FFT_SIZE], so the window covers the end of earlier buffers too.
+    history: Vec<f32>,
+
+    buffer: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+
+    /// Magnitude of each FFT bin
+    magnitudes: Vec<f32>,
+
+    bands: Vec<f32>,
+    smoothed: Vec<f32>,
+    peaks: Vec<f32>,
+    normalized: Vec<f32>,
+
+    /// Running maximum of the smoothed bands used for normalization
+    level: f32,
+
+    /// Sum of absolute band changes of the last analysis
+    flux: f32,
+
+    /// Sum of the increases of log-compressed bands of the last analysis
+    onset: f32,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let scratch = vec![Complex::default(); fft.get_inplace_scratch_len()];
+
+        // Hann window normalized so that a sine has its amplitude as magnitude
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (core::f32::consts::TAU * i as f32 / FFT_SIZE as f32).cos())
+            .collect::<Vec<_>>();
+        let sum = window.iter().sum::<f32>();
+        let window = window.into_iter().map(|w| w / sum).collect();
+
+        Self {
+            fft,
+            window,
+            history: vec![0.; FFT_SIZE],
+            buffer: vec![Complex::default(); FFT_SIZE],
+            scratch,
+            magnitudes: vec![0.; FFT_SIZE / 2],
+            bands: Vec::new(),
+            smoothed: Vec::new(),
+            peaks: Vec::new(),
+            normalized: Vec::new(),
+            level: 0.,
+            flux: 0.,
+            onset: 0.,
+        }
+    }
+}
+
+impl fmt::Debug for Spectrum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spectrum")
+            .field("bands", &self.bands)
+            .field("smoothed", &self.smoothed)
+            .field("peaks", &self.peaks)
+            .field("level", &self.level)
+            .field("flux", &self.flux)
+            .field("onset", &self.onset)
+            .finish()
+    }
+}
+
+impl Spectrum {
+    /// Analyzes interleaved stereo samples at [ANALYSIS_SAMPLE_RATE]. The FFT covers the last
+    /// [FFT_SIZE] frames, including those of earlier calls if the buffer is shorter.
+    pub fn analyze(&mut self, stereo: &[f32], s: &SpectrumSettings) {
+        if self.bands.len() != s.bands {
+            self.resize(s.bands);
+        }
+
+        let frames = stereo.len() / 2;
+        let skip = frames.saturating_sub(FFT_SIZE);
+        self.history.rotate_left(frames - skip);
+        let start = FFT_SIZE - (frames - skip);
+        for (h, frame) in self.history[start..].iter_mut().zip(stereo.chunks_exact(2).skip(skip)) {
+            *h = 0.5 * (frame[0] + frame[1]);
+        }
+        for ((b, h), w) in self.buffer.iter_mut().zip(&self.history).zip(&self.window) {
+            *b = Complex::new(h * w, 0.);
+        }
+
+        self.fft.process_with_scratch(&mut self.buffer, &mut self.scratch);
+
+        for (m, c) in self.magnitudes.iter_mut().zip(&self.buffer) {
+            *m = 2. * c.norm();
+        }
+
+        self.flux = 0.;
+        self.onset = 0.;
+        for k in 0..s.bands {
+            let band = self.band_magnitude(k, s);
+            self.flux += (band - self.bands[k]).abs();
+            self.onset += (compress(band) - compress(self.bands[k])).max(0.);
+            self.bands[k] = band;
+            self.smoothed[k] = s.smoothing * self.smoothed[k] + (1. - s.smoothing) * band;
+            self.peaks[k] = band.max(self.peaks[k] * s.peak_decay);
+        }
+
+        let max = self.smoothed.iter().copied().fold(0., f32::max);
+        self.level = max.max(self.level * s.normalization_decay);
+        let z = if self.level > 1e-6 { 1. / self.level } else { 0. };
+        for (n, v) in self.normalized.iter_mut().zip(&self.smoothed) {
+            *n = (v * z).min(1.);
+        }
+    }
+
+    /// Forgets all past analysis
+    pub fn reset(&mut self) {
+        self.history.fill(0.);
+        self.bands.fill(0.);
+        self.smoothed.fill(0.);
+        self.peaks.fill(0.);
+        self.normalized.fill(0.);
+        self.level = 0.;
+        self.flux = 0.;
+        self.onset = 0.;
+    }
+
+    /// Band magnitudes of the last analysis
+    pub fn bands(&self) -> &[f32] {
+        &self.bands
+    }
+
+    /// Band magnitudes smoothed over time
+    pub fn smoothed(&self) -> &[f32] {
+        &self.smoothed
+    }
+
+    /// Recent maxima of the band magnitudes
+    pub fn peaks(&self) -> &[f32] {
+        &self.peaks
+    }
+
+    /// Smoothed band magnitudes relative to the recent loudest band (0.0 to 1.0)
+    pub fn normalized(&self) -> &[f32] {
+        &self.normalized
+    }
+
+    /// Normalization level, i.e. the recent maximum of the smoothed bands
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// Sum of absolute changes of all bands between the last two analyses
+    pub fn flux(&self) -> f32 {
+        self.flux
+    }
+
+    /// Onset strength: how much the bands rose between the last two analyses. Unlike [Self::flux]
+    /// it ignores falling bands and is compressed so that quiet onsets count too.
+    pub fn onset(&self) -> f32 {
+        self.onset
+    }
+
+    /// Mean of normalized bands in the lowest third of the spectrum
+    pub fn bass(&self) -> f32 {
+        self.range_mean(0, 3)
+    }
+
+    /// Mean of normalized bands in the middle third of the spectrum
+    pub fn mid(&self) -> f32 {
+        self.range_mean(1, 3)
+    }
+
+    /// Mean of normalized bands in the upper third of the spectrum
+    pub fn treble(&self) -> f32 {
+        self.range_mean(2, 3)
+    }
+
+    fn range_mean(&self, part: usize, parts: usize) -> f32 {
+        let n = self.normalized.len();
+        let range = &self.normalized[part * n / parts..(part + 1) * n / parts];
+        if range.is_empty() { 0. } else { range.iter().sum::<f32>() / range.len() as f32 }
+    }
+
+    fn resize(&mut self, bands: usize) {
+        self.bands = vec![0.; bands];
+        self.smoothed = vec![0.; bands];
+        self.peaks = vec![0.; bands];
+        self.normalized = vec![0.; bands];
+    }
+
+    /// Maximum magnitude of all bins within band `k`. Narrow bands which contain no bin use the bin
+    /// closest to their center.
+    fn band_magnitude(&self, k: usize, s: &SpectrumSettings) -> f32 {
+        let ratio = s.max_freq / s.min_freq;
+        let edge = |k: usize| s.min_freq * ratio.powf(k as f32 / s.bands as f32);
+        let hz_per_bin = ANALYSIS_SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        let last = self.magnitudes.len() - 1;
+
+        let lo = ((edge(k) / hz_per_bin).ceil() as usize).min(last);
+        let hi = ((edge(k + 1) / hz_per_bin).floor() as usize).min(last);
+        if lo <= hi {
+            self.magnitudes[lo..=hi].iter().copied().fold(0., f32::max)
+        } else {
+            let center = (edge(k) * edge(k + 1)).sqrt();
+            self.magnitudes[((center / hz_per_bin).round() as usize).min(last)]
+        }
+    }
+}
+
+/// Logarithmic compression of band magnitudes
+fn compress(v: f32) -> f32 {
+    (1000. * v).ln_1p()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds a sine in buffers of the size the painter uses at 30 fps
+    fn analyze_sine(freq: f32, amplitude: f32) -> (Spectrum, SpectrumSettings) {
+        let s = SpectrumSettings { smoothing: 0., ..Default::default() };
+        let mut spectrum = Spectrum::default();
+        let rate = ANALYSIS_SAMPLE_RATE as f32;
+        let mut t = 0;
+        for _ in 0..4 {
+            let stereo = (0..641)
+                .flat_map(|_| {
+                    let v = amplitude * (core::f32::consts::TAU * freq * t as f32 / rate).sin();
+                    t += 1;
+                    [v, v]
+                })
+                .collect::<Vec<_>>();
+            spectrum.analyze(&stereo, &s);
+        }
+        (spectrum, s)
+    }
+
+    #[test]
+    fn sine_has_its_amplitude_in_its_band() {
+        // centered on an FFT bin, so there's no scalloping loss
+        let freq = 24. * ANALYSIS_SAMPLE_RATE as f32 / FFT_SIZE as f32;
+        let (spectrum, s) = analyze_sine(freq, 0.5);
+
+        let ratio = s.max_freq / s.min_freq;
+        let band = (0..s.bands)
+            .find(|&k| s.min_freq * ratio.powf((k + 1) as f32 / s.bands as f32) > freq)
+            .unwrap();
+        let bands = spectrum.bands();
+        assert!((bands[band] - 0.5).abs() < 0.01, "{bands:?}");
+        for (k, v) in bands.iter().enumerate() {
+            if k.abs_diff(band) > 1 {
+                assert!(*v < 0.01, "band {k}: {bands:?}");
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+/// Range of detected tempos in beats per minute
+const MIN_BPM: f32 = 60.;
+const MAX_BPM: f32 = 200.;
+
+/// Tempos around this are preferred when deciding between multiples of the beat period
+const PREFERRED_BPM: f32 = 120.;
+
+/// Seconds of onset history used to estimate the tempo
+const HISTORY_SECS: f32 = 8.;
+
+/// Number of beat periods compared by the comb filter. More periods give a more precise tempo.
+const COMB_PERIODS: usize = 4;
+
+/// The tempo is estimated again after this many updates
+const ESTIMATE_EVERY: u32 = 8;
+
+/// Confidence above which the tracker is considered locked to the beat
+const LOCK_CONFIDENCE: f32 = 0.3;
+
+/// Estimates tempo and beat phase from the onset strength of the spectrum.
+///
+/// Onsets are collected into an envelope. The autocorrelation of the envelope is searched for the
+/// beat period which best explains several consecutive periods. The phase is then found by
+/// folding the envelope with this period.
+#[derive(Debug, Default)]
+pub struct Tempo {
+    /// Onset strength of recent updates, oldest first
+    envelope: VecDeque<f32>,
+
+    /// Average seconds between updates
+    dt: f32,
+
+    /// Updates since the last estimation
+    since_estimate: u32,
+
+    /// Beat period in seconds or 0 if unknown
+    period: f32,
+
+    /// Estimated periods which disagree with `period` in a row
+    disagreements: u32,
+
+    confidence: f32,
+    phase: f32,
+    beats: u64,
+}
+
+impl Tempo {
+    /// Adds the onset strength of `dt` seconds of audio
+    pub fn update(&mut self, onset: f32, dt: f32) {
+        if dt <= 0. {
+            return;
+        }
+
+        self.dt = if self.dt > 0. { 0.95 * self.dt + 0.05 * dt } else { dt };
+        self.envelope.push_back(onset);
+        let capacity = (HISTORY_SECS / self.dt) as usize;
+        while self.envelope.len() > capacity {
+            self.envelope.pop_front();
+        }
+
+        if self.period > 0. {
+            self.phase += dt / self.period;
+            if self.phase >= 1. {
+                self.phase = self.phase.fract();
+                self.beats += 1;
+            }
+        }
+
+        self.since_estimate += 1;
+        if self.since_estimate >= ESTIMATE_EVERY {
+            self.since_estimate = 0;
+            self.estimate();
+        }
+    }
+
+    /// Forgets all past onsets
+    pub fn reset(&mut self) {
+        *self = Self { beats: self.beats, ..Default::default() };
+    }
+
+    /// Tempo in beats per minute if one was detected
+    pub fn bpm(&self) -> Option<f32> {
+        (self.period > 0.).then(|| 60. / self.period)
+    }
+
+    /// How well the onsets fit the detected tempo (0.0 to 1.0)
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// True if the tempo is known with reasonable confidence
+    pub fn is_locked(&self) -> bool {
+        self.period > 0. && self.confidence >= LOCK_CONFIDENCE
+    }
+
+    /// Position within the current beat (0.0 on the beat to 1.0 just before the next one)
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    /// Number of beats since the start
+    pub fn beats(&self) -> u64 {
+        self.beats
+    }
+
+    /// 1.0 on the beat decaying to 0.0 until the next beat. Always 0.0 when not locked.
+    pub fn pulse(&self) -> f32 {
+        if self.is_locked() { (1. - self.phase).powi(3) } else { 0. }
+    }
+
+    fn estimate(&mut self) {
+        let x = {
+            let mean = self.envelope.iter().sum::<f32>() / self.envelope.len().max(1) as f32;
+            self.envelope.iter().map(|v| v - mean).collect::<Vec<_>>()
+        };
+
+        // lags need at least two periods in the half of the history which gives reliable
+        // autocorrelation values
+        let lags = (x.len() / 2).min((60. / (MIN_BPM * self.dt)) as usize * COMB_PERIODS + 2);
+        let min_lag = 60. / (MAX_BPM * self.dt);
+        let max_lag = (60. / (MIN_BPM * self.dt)).min((lags - 2) as f32 / 2.);
+        if max_lag < min_lag {
+            return;
+        }
+
+        let r = autocorrelation(&x, lags);
+        if r[0] <= 1e-9 {
+            self.confidence = 0.;
+            return;
+        }
+
+        // search fractional periods on a fine grid
+        let comb = |period: f32| {
+            let periods = (((lags - 2) as f32 / period) as usize).min(COMB_PERIODS);
+            (1..=periods).map(|k| interpolate(&r, k as f32 * period)).sum::<f32>()
+                / (periods as f32 * r[0])
+        };
+        let mut best = (0., f32::MIN, 0.);
+        let mut lag = min_lag;
+        while lag <= max_lag {
+            let bpm = 60. / (lag * self.dt);
+            let octaves = (bpm / PREFERRED_BPM).log2();
+            let weight = (-0.5 * octaves * octaves).exp();
+            let fit = comb(lag);
+            if fit * weight > best.1 {
+                best = (lag, fit * weight, fit);
+            }
+            lag += 0.05;
+        }
+
+        let (lag, _, fit) = best;
+        self.confidence = fit.clamp(0., 1.);
+        let period = lag * self.dt;
+
+        if self.period == 0. || (period / self.period - 1.).abs() < 0.04 {
+            self.period = if self.period == 0. { period } else { 0.7 * self.period + 0.3 * period };
+            self.disagreements = 0;
+        } else {
+            self.disagreements += 1;
+            if self.disagreements >= 4 {
+                self.period = period;
+                self.disagreements = 0;
+            }
+        }
+
+        self.correct_phase(&x);
+    }
+
+    /// Pulls the phase towards the offset at which the folded envelope is strongest
+    fn correct_phase(&mut self, x: &[f32]) {
+        let period = self.period / self.dt;
+        let newest = x.len() as f32 - 1.;
+
+        let mut best = (0., f32::MIN);
+        let mut offset = 0.;
+        while offset < period {
+            let mut sum = 0.;
+            let mut i = newest - offset;
+            while i >= 0. {
+                sum += interpolate(x, i);
+                i -= period;
+            }
+            if sum > best.1 {
+                best = (offset, sum);
+            }
+            offset += 0.25;
+        }
+
+        let measured = best.0 / period;
+        // shortest signed distance on the circle
+        let error = (measured - self.phase + 0.5).rem_euclid(1.) - 0.5;
+        self.phase = (self.phase + 0.3 * error).rem_euclid(1.);
+    }
+}
+
+/// Unnormalized autocorrelation for lags `0..lags`
+fn autocorrelation(x: &[f32], lags: usize) -> Vec<f32> {
+    (0..lags)
+        .map(|lag| {
+            let n = x.len() - lag;
+            x[..n].iter().zip(&x[lag..]).map(|(a, b)| a * b).sum::<f32>() / n as f32
+        })
+        .collect()
+}
+
+/// Linear interpolation of `v` at a fractional index
+fn interpolate(v: &[f32], i: f32) -> f32 {
+    let i0 = (i.floor() as usize).min(v.len() - 1);
+    let i1 = (i0 + 1).min(v.len() - 1);
+    let f = i - i0 as f32;
+    v[i0] * (1. - f) + v[i1] * f
+}
@@ -0,0 +1,84 @@
+use crate::painter::WarpMap;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransitionSettings {
+    /// Number of frames over which the previous warp map fades into the new one. 0 switches
+    /// instantly like the original.
+    pub frames: u32,
+
+    /// Shape of the fade
+    pub curve: TransitionCurve,
+}
+
+impl Default for TransitionSettings {
+    fn default() -> Self {
+        Self { frames: 45, curve: TransitionCurve::SmoothStep }
+    }
+}
+
+/// Maps the progress of a transition (0.0 to 1.0) to the weight of the new warp map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionCurve {
+    Linear,
+    #[default]
+    SmoothStep,
+    EaseIn,
+    EaseOut,
+}
+
+impl TransitionCurve {
+    pub const ALL: [TransitionCurve; 4] = [
+        TransitionCurve::Linear,
+        TransitionCurve::SmoothStep,
+        TransitionCurve::EaseIn,
+        TransitionCurve::EaseOut,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransitionCurve::Linear => "Linear",
+            TransitionCurve::SmoothStep => "Smooth Step",
+            TransitionCurve::EaseIn => "Ease In",
+            TransitionCurve::EaseOut => "Ease Out",
+        }
+    }
+
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            TransitionCurve::Linear => t,
+            TransitionCurve::SmoothStep => t * t * (3. - 2. * t),
+            TransitionCurve::EaseIn => t * t,
+            TransitionCurve::EaseOut => t * (2. - t),
+        }
+    }
+}
+
+/// Cross-fade from the warp map which was active before the last mode switch
+pub struct Transition {
+    /// The previous warp map
+    pub from: WarpMap,
+
+    /// Frames since the mode switch
+    pub frame: u32,
+}
+
+impl Transition {
+    pub fn new(from: WarpMap) -> Self {
+        Self { from, frame: 0 }
+    }
+
+    /// Weight of the new warp map for the next frame in 1/256. Returns None once the transition is
+    /// over.
+    pub fn step(&mut self, s: &TransitionSettings) -> Option<u32> {
+        self.frame += 1;
+        if self.frame > s.frames {
+            return None;
+        }
+        let t = self.frame as f32 / (s.frames + 1) as f32;
+        Some((s.curve.apply(t) * 256.) as u32)
+    }
+}
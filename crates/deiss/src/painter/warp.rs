@@ -1,6 +1,10 @@
 use crate::{painter::*, utils::*};
+use rayon::prelude::*;
 
-#[derive(Clone, Copy, Default)]
+/// Source of an output pixel: the weights of the 2x2 block of source pixels starting at `index`.
+/// Aligned so that a pixel is a single 8 byte load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C, align(8))]
 pub struct WarpPixel {
     pub weights: [u8; 4],
     pub index: u32,
@@ -18,20 +22,35 @@ pub struct WarpSpec {
     pub weightsum: f32,
     pub damping: f32,
     pub tf: AnyTransform,
+
+    /// State of the random number generator `tf` was generated with, to generate it again
+    pub tf_rand: Minstd,
+
+    /// Colors to switch to when the spec is shown, see [Settings::gf]
+    pub gf: Option<[f32; 6]>,
 }
 
 impl WarpSpec {
     pub fn generate(s: &Settings, fx: &ModeBlueprintLibrary, g: &mut Globals) -> Self {
         let mode = s.mode_prefs.pick(&mut g.rand);
+        Self::generate_mode(mode, s, fx, g)
+    }
 
+    /// Like [Self::generate] but for the given mode instead of picking one
+    pub fn generate_mode(
+        mode: ModeId,
+        s: &Settings,
+        fx: &ModeBlueprintLibrary,
+        g: &mut Globals,
+    ) -> Self {
         let [effects_min, effects_max] = fx[mode].effect_count;
         let effects =
             fx[mode].effect_freq.sample((effects_min as usize, effects_max as usize), &mut g.rand);
 
-        let center = Vec2::new(
-            ((s.fxw / 2 - 1) + g.rand.next_idx(60) - 30) as i32,
-            ((s.fxh / 2 - 1) + g.rand.next_idx(30) - 15) as i32,
-        );
+        // random offset of up to 30 by 15 reference pixels
+        let dx = g.rand.next_idx(60) as i32 - 30;
+        let dy = g.rand.next_idx(30) as i32 - 15;
+        let center = center_from_offset(s, Vec2::new(dx, dy).cast());
 
         let damping = g.suggested_dampening.clamp(0.50, 1.00)
             * if fx[mode].motion_dampened { 0.5 } else { 1.0 }
@@ -41,7 +60,9 @@ impl WarpSpec {
 
         g.big_beat_threshold = 1.10; // ??
 
-        let tf = fx[mode].generate_transform(&mut g.rand);
+        let inputs = TransformInputs::new(g);
+        let tf_rand = g.rand.clone();
+        let tf = fx[mode].generate_transform(&mut g.rand, &inputs);
 
         let weightsum = match mode {
             ModeId(12) => 0.98,
@@ -49,10 +70,43 @@ impl WarpSpec {
             _ => 1.,
         };
 
-        WarpSpec { settings: s.clone(), effects, mode, waveform, center, weightsum, damping, tf }
+        WarpSpec {
+            settings: s.clone(),
+            effects,
+            mode,
+            waveform,
+            center,
+            weightsum,
+            damping,
+            tf,
+            tf_rand,
+            gf: None,
+        }
     }
 }
 
+/// Scale of center offsets given in reference pixels. They are stretched to the painted area.
+fn center_offset_scale(s: &Settings) -> Vec2f {
+    Vec2::new(
+        s.fxw as f32 / REFERENCE_WIDTH as f32,
+        s.y_roi.rows() as f32 / REFERENCE_Y_ROI.rows() as f32,
+    )
+}
+
+/// Center of a mode which is offset from the middle of the image by `offset` reference pixels
+pub fn center_from_offset(s: &Settings, offset: Vec2f) -> Vec2i {
+    let k = center_offset_scale(s);
+    let (dx, dy) = (offset.x * k.x, offset.y * k.y);
+    Vec2::new((s.fxw / 2 - 1) as i32 + dx as i32, (s.fxh / 2 - 1) as i32 + dy as i32)
+}
+
+/// Offset of a center from the middle of the image in reference pixels, see [center_from_offset]
+pub fn center_offset(s: &Settings, center: Vec2i) -> Vec2f {
+    let k = center_offset_scale(s);
+    let (dx, dy) = (center.x - (s.fxw / 2 - 1) as i32, center.y - (s.fxh / 2 - 1) as i32);
+    Vec2::new(dx as f32 / k.x, dy as f32 / k.y)
+}
+
 pub struct WarpGen {
     spec: WarpSpec,
 }
@@ -62,7 +116,7 @@ impl WarpGen {
         WarpGen { spec }
     }
 
-    pub fn run(&mut self) -> WarpMap {
+    pub fn run(&mut self) -> BakedWarp {
         bake(
             &self.spec.settings,
             self.spec.center.cast(),
@@ -73,62 +127,176 @@ impl WarpGen {
     }
 }
 
-pub fn bake<M: GeneralPixelTransform>(
+/// Source position of each pixel, i.e. a warp map before it is quantized
+pub type SourceField = Image<Vec2f>;
+
+/// Result of baking a [WarpSpec]
+pub struct BakedWarp {
+    pub map: WarpMap,
+
+    /// The field the map was quantized from. Kept to modulate the map while the mode is shown.
+    pub field: SourceField,
+}
+
+pub fn bake<M: GeneralPixelTransform + Sync>(
     s: &Settings,
     center: Vec2f,
     weightsum_factor: f32,
     damping: f32,
     mode: &M,
-) -> Image<WarpPixel> {
-    let s_fxw_minus_once = (s.fxw - 1) as f32;
-    // let half_fxw = s.fxw as f32 * 0.5;
+) -> BakedWarp {
+    let field = bake_field(s, center, damping, mode);
+    let map = quantize(s, weightsum_factor, &field, |p| p);
+    BakedWarp { map, field }
+}
 
-    // TODO original varies this based on resolution
-    let weightsum_res_adjusted = weightsum_factor * 252.5;
+/// Single-threaded version of [bake]. Reference for [crate::headless::run_bench].
+pub fn bake_scalar<M: GeneralPixelTransform>(
+    s: &Settings,
+    center: Vec2f,
+    weightsum_factor: f32,
+    damping: f32,
+    mode: &M,
+) -> BakedWarp {
+    let shape = Vec2f::new(s.fxw as f32, s.fxh as f32);
+    let field = Image::from_fn((s.fxh, s.fxw).into(), |(i, j)| {
+        source_position(Vec2f { x: j as f32, y: i as f32 }, center, shape, damping, mode)
+    });
+    let weightsum = weightsum_factor * WEIGHTSUM_SCALE;
+    let map = Image::from_fn(field.shape(), |coo| quantize_pixel(s, weightsum, field[coo]));
+    BakedWarp { map, field }
+}
 
+/// Evaluates the transform for every pixel
+pub fn bake_field<M: GeneralPixelTransform + Sync>(
+    s: &Settings,
+    center: Vec2f,
+    damping: f32,
+    mode: &M,
+) -> SourceField {
     let shape = Vec2f::new(s.fxw as f32, s.fxh as f32);
 
-    Image::from_fn((s.fxh, s.fxw).into(), |(i, j)| {
-        let pi = Vec2f { x: j as f32, y: i as f32 };
+    Image::par_from_fn((s.fxh, s.fxw).into(), |(i, j)| {
+        source_position(Vec2f { x: j as f32, y: i as f32 }, center, shape, damping, mode)
+    })
+}
 
-        let p2 = mode.transform(pi, center, shape);
+/// Like [bake_field] but evaluates the transform only on a grid with cells of `cell` pixels and
+/// interpolates in between. Much faster but smooths out details smaller than a cell.
+pub fn bake_field_coarse<M: GeneralPixelTransform + Sync>(
+    s: &Settings,
+    center: Vec2f,
+    damping: f32,
+    mode: &M,
+    cell: u32,
+) -> SourceField {
+    let shape = Vec2f::new(s.fxw as f32, s.fxh as f32);
 
-        let p4 = pi * (1.0 - damping) + p2 * damping;
+    let nodes = Image::par_from_fn(
+        (s.fxh.div_ceil(cell) + 1, s.fxw.div_ceil(cell) + 1).into(),
+        |(i, j)| {
+            let pi = Vec2f { x: (j * cell) as f32, y: (i * cell) as f32 };
+            source_position(pi, center, shape, damping, mode)
+        },
+    );
+
+    Image::par_from_fn((s.fxh, s.fxw).into(), |(i, j)| {
+        let (gi, gj) = (i / cell, j / cell);
+        let fy = (i % cell) as f32 / cell as f32;
+        let fx = (j % cell) as f32 / cell as f32;
+        let top = nodes[(gi, gj)] * (1. - fx) + nodes[(gi, gj + 1)] * fx;
+        let bottom = nodes[(gi + 1, gj)] * (1. - fx) + nodes[(gi + 1, gj + 1)] * fx;
+        top * (1. - fy) + bottom * fy
+    })
+}
 
-        let p = {
-            let mut p = p4;
-            while p.x < 0. {
-                p.x += s_fxw_minus_once;
-            }
-            while p.x > s_fxw_minus_once {
-                p.x -= s_fxw_minus_once;
-            }
-            p
-        };
+/// Damped source position of the pixel at `pi`
+fn source_position<M: GeneralPixelTransform>(
+    pi: Vec2f,
+    center: Vec2f,
+    shape: Vec2f,
+    damping: f32,
+    mode: &M,
+) -> Vec2f {
+    let p2 = mode.transform(pi, center, shape);
+    pi * (1.0 - damping) + p2 * damping
+}
 
-        let ix = p.x as u32;
-        let iy = p.y as u32;
+/// Sum of the bilinear weights of a pixel in 1/256, i.e. how much of the image is kept per frame.
+/// The original varied this with the resolution. The fade per frame does not depend on the
+/// resolution though, so it is the same at every resolution.
+const WEIGHTSUM_SCALE: f32 = 252.5;
 
-        // exclude bottom and top two rows
-        let index = iy.clamp(2, s.fxh - 3) * s.fxw + ix;
+/// Converts source positions into a warp map. `modulate` is applied to each source position first.
+pub fn quantize(
+    s: &Settings,
+    weightsum_factor: f32,
+    field: &SourceField,
+    modulate: impl Fn(Vec2f) -> Vec2f + Sync,
+) -> WarpMap {
+    let weightsum = weightsum_factor * WEIGHTSUM_SCALE;
+    Image::par_from_fn(field.shape(), |coo| quantize_pixel(s, weightsum, modulate(field[coo])))
+}
 
-        let weightsum_this_pixel = weightsum_res_adjusted;
+fn quantize_pixel(s: &Settings, weightsum: f32, p: Vec2f) -> WarpPixel {
+    let s_fxw_minus_once = (s.fxw - 1) as f32;
 
-        let dx = p.x - ix as f32;
-        let dy = p.y - iy as f32;
+    let p = {
+        let mut p = p;
+        while p.x < 0. {
+            p.x += s_fxw_minus_once;
+        }
+        while p.x > s_fxw_minus_once {
+            p.x -= s_fxw_minus_once;
+        }
+        p
+    };
+
+    let ix = p.x as u32;
+    let iy = p.y as u32;
+
+    // exclude bottom and top two rows
+    let index = iy.clamp(2, s.fxh - 3) * s.fxw + ix;
+
+    let dx = p.x - ix as f32;
+    let dy = p.y - iy as f32;
+
+    let weights = [
+        ((1. - dx) * (1. - dy) * weightsum) as u8,
+        (dx * (1. - dy) * weightsum) as u8,
+        ((1. - dx) * dy * weightsum) as u8,
+        (dx * dy * weightsum) as u8,
+    ];
+
+    WarpPixel { weights, index }
+}
 
-        let weights = [
-            ((1. - dx) * (1. - dy) * weightsum_this_pixel) as u8,
-            (dx * (1. - dy) * weightsum_this_pixel) as u8,
-            ((1. - dx) * dy * weightsum_this_pixel) as u8,
-            (dx * dy * weightsum_this_pixel) as u8,
-        ];
+/// Applies a warp map to the painted rows. With a single rayon thread this is
+/// [process_map_scalar], which is as fast as the packed kernel on one core.
+pub fn process_map(s: &Settings, fx: &[WarpPixel], src: &RgbaImage, dst: &mut RgbaImage) {
+    if rayon::current_num_threads() == 1 {
+        return process_map_scalar(s, fx, src, dst);
+    }
 
-        WarpPixel { weights, index }
-    })
+    let cols = s.fxw as usize;
+    let src = src.as_slice();
+    let dst = dst.as_slice_mut();
+
+    let idx0 = (s.fxw * s.y_roi.min) as usize;
+    let idx1 = (s.fxw * s.y_roi.max) as usize;
+
+    dst[idx0..idx1].par_chunks_mut(cols).zip(fx[idx0..idx1].par_chunks(cols)).for_each(
+        |(dst, fx)| {
+            for (d, &WarpPixel { weights, index }) in dst.iter_mut().zip(fx) {
+                *d = bilin_packed(src, index as usize, cols, weights);
+            }
+        },
+    );
 }
 
-pub fn process_map(s: &Settings, fx: &[WarpPixel], src: &RgbaImage, dst: &mut RgbaImage) {
+/// Single-threaded version of [process_map] which works on each channel separately. Reference for
+/// [crate::headless::run_bench].
+pub fn process_map_scalar(s: &Settings, fx: &[WarpPixel], src: &RgbaImage, dst: &mut RgbaImage) {
     let src = src.as_slice();
     let dst = dst.as_slice_mut();
 
@@ -141,6 +309,58 @@ pub fn process_map(s: &Settings, fx: &[WarpPixel], src: &RgbaImage, dst: &mut Rg
     }
 }
 
+/// Like [process_map] but blends the outputs of two warp maps. `weight` is the weight of `to` in
+/// 1/256.
+pub fn process_map_blend(
+    s: &Settings,
+    from: &[WarpPixel],
+    to: &[WarpPixel],
+    weight: u32,
+    src: &RgbaImage,
+    dst: &mut RgbaImage,
+) {
+    let cols = s.fxw as usize;
+    let src = src.as_slice();
+    let dst = dst.as_slice_mut();
+
+    let idx0 = (s.fxw * s.y_roi.min) as usize;
+    let idx1 = (s.fxw * s.y_roi.max) as usize;
+
+    let weight = weight.min(256);
+    let blend =
+        |from: &WarpPixel, to: &WarpPixel, bilin: fn(&[Rgba], usize, usize, [u8; 4]) -> Rgba| {
+            let a = bilin(src, from.index as usize, cols, from.weights);
+            let b = bilin(src, to.index as usize, cols, to.weights);
+            Rgba([
+                mix_u8(a[0], b[0], weight),
+                mix_u8(a[1], b[1], weight),
+                mix_u8(a[2], b[2], weight),
+                255,
+            ])
+        };
+
+    if rayon::current_num_threads() == 1 {
+        for idx in idx0..idx1 {
+            dst[idx] = blend(&from[idx], &to[idx], bilin_w);
+        }
+        return;
+    }
+
+    dst[idx0..idx1]
+        .par_chunks_mut(cols)
+        .zip(from[idx0..idx1].par_chunks(cols))
+        .zip(to[idx0..idx1].par_chunks(cols))
+        .for_each(|((dst, from), to)| {
+            for ((d, from), to) in dst.iter_mut().zip(from).zip(to) {
+                *d = blend(from, to, bilin_packed);
+            }
+        });
+}
+
+fn mix_u8(a: u8, b: u8, weight: u32) -> u8 {
+    ((a as u32 * (256 - weight) + b as u32 * weight) >> 8) as u8
+}
+
 fn bilin_w(src: &[Rgba], i: usize, cols: usize, weights: [u8; 4]) -> Rgba {
     let p1 = src[i];
     let p2 = src[i + 1];
@@ -162,3 +382,106 @@ fn dot_u8(a: [u8; 4], b: [u8; 4]) -> u8 {
         + (a[3] as u32) * (b[3] as u32))
         >> 8) as u8
 }
+
+/// Same result as [bilin_w] but computes two channels per multiplication: each pixel is split
+/// into two words with a channel in every 16-bit lane. Since the weights sum to at most 256 the
+/// weighted sums never carry into the next lane. On a single core it is about as fast as
+/// [bilin_w], so it is only used by the parallel versions.
+fn bilin_packed(src: &[Rgba], i: usize, cols: usize, weights: [u8; 4]) -> Rgba {
+    const LANES: u32 = 0x00ff_00ff;
+
+    let pixels = [src[i], src[i + 1], src[i + cols], src[i + cols + 1]];
+    let mut even = 0_u32;
+    let mut odd = 0_u32;
+    for (p, w) in pixels.iter().zip(weights) {
+        let p = u32::from_le_bytes(p.0);
+        even += (p & LANES) * w as u32;
+        odd += ((p >> 8) & LANES) * w as u32;
+    }
+
+    let mut out = (((even >> 8) & LANES) | (odd & !LANES)).to_le_bytes();
+    out[3] = 255;
+    Rgba(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `f` on a rayon pool with several threads so that the parallel versions are used even
+    /// on a single core
+    fn parallel<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+        rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap().install(f)
+    }
+
+    /// Image in which every pixel has a different value so that any wrong weight shows
+    fn noise(shape: Shape2, rand: &mut Minstd) -> RgbaImage {
+        let mut img = RgbaImage::from_value(shape, Rgba::BLACK);
+        img.apply(|_| {
+            let v = rand.next_idx(1 << 24);
+            Rgba([v as u8, (v >> 8) as u8, (v >> 16) as u8, 255])
+        });
+        img
+    }
+
+    fn assert_parallel_matches_scalar(shape: Shape2) {
+        let mut painter = Painter::new(shape, Clock::fixed(30.), Some(3));
+        let s = &painter.settings;
+        let g = &mut painter.globals;
+        let src = noise(s.shape(), &mut g.rand);
+
+        let maps = [1, 2, 3, 5, 6, 8, 9, 10, 11, 12].map(|mode| {
+            let spec = WarpSpec::generate_mode(ModeId(mode), s, &painter.library, g);
+            let center = spec.center.cast();
+            let scalar = bake_scalar(s, center, spec.weightsum, spec.damping, &spec.tf);
+            let baked = parallel(|| bake(s, center, spec.weightsum, spec.damping, &spec.tf));
+            assert!(baked.map.as_slice() == scalar.map.as_slice(), "bake of mode {mode}");
+            assert!(baked.field.as_slice() == scalar.field.as_slice(), "field of mode {mode}");
+
+            let mut expected = src.clone();
+            process_map_scalar(s, scalar.map.as_slice(), &src, &mut expected);
+            let mut actual = src.clone();
+            parallel(|| process_map(s, baked.map.as_slice(), &src, &mut actual));
+            assert!(actual.as_bytes() == expected.as_bytes(), "process_map of mode {mode}");
+            (mode, scalar.map, expected)
+        });
+
+        for pair in maps.windows(2) {
+            let [(from_mode, from, from_img), (to_mode, to, to_img)] = pair else { unreachable!() };
+            for weight in [0, 77, 256] {
+                let mut expected = src.clone();
+                for idx in (s.fxw * s.y_roi.min) as usize..(s.fxw * s.y_roi.max) as usize {
+                    let (a, b) = (from_img.as_slice()[idx], to_img.as_slice()[idx]);
+                    expected.as_slice_mut()[idx] = Rgba([
+                        mix_u8(a[0], b[0], weight),
+                        mix_u8(a[1], b[1], weight),
+                        mix_u8(a[2], b[2], weight),
+                        255,
+                    ]);
+                }
+
+                let mut actual = src.clone();
+                let (from, to) = (from.as_slice(), to.as_slice());
+                parallel(|| process_map_blend(s, from, to, weight, &src, &mut actual));
+                assert!(
+                    actual.as_bytes() == expected.as_bytes(),
+                    "blend of modes {from_mode} and {to_mode} at {weight}"
+                );
+
+                let mut single = src.clone();
+                process_map_blend(s, from, to, weight, &src, &mut single);
+                assert!(single.as_bytes() == expected.as_bytes(), "single-threaded blend");
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_matches_scalar_at_reference_resolution() {
+        assert_parallel_matches_scalar((480, 640).into());
+    }
+
+    #[test]
+    fn parallel_matches_scalar_at_wide_resolution() {
+        assert_parallel_matches_scalar((450, 800).into());
+    }
+}
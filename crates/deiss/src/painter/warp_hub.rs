@@ -1,59 +1,176 @@
-use crate::{
-    painter::{
-        globals::Globals,
-        mode_blueprint_library::ModeBlueprintLibrary,
-        settings::Settings,
-        warp::{WarpGen, WarpMap, WarpPixel, WarpSpec},
-    },
-    utils::*,
+use crate::painter::{
+    bookmark::Bookmark,
+    globals::Globals,
+    mode_blueprint_library::ModeBlueprintLibrary,
+    mode_id::ModeId,
+    mode_switch::{SwitchPolicy, SwitchSettings},
+    settings::Settings,
+    warp::{BakedWarp, WarpGen, WarpSpec},
 };
 use eyre::{Result, bail};
-use std::{
-    sync::mpsc,
-    thread::JoinHandle,
-    time::{Duration, Instant},
-};
+use std::{mem, sync::mpsc, thread::JoinHandle};
 
 pub struct WarpMapHub {
-    current: Option<(WarpSpec, WarpMap)>,
+    /// Map which becomes active, taken by [Self::fetch]
+    current: Option<(WarpSpec, BakedWarp)>,
+    /// Map which is baked ahead of time and waits for the switch
+    ready: Option<(WarpSpec, BakedWarp)>,
     next_spec: Option<WarpSpec>,
     worker: WarpMapWorker,
-    next_switch_time: Instant,
+    /// True if the map which is being baked was started before a restart and must be dropped
+    stale: bool,
+    /// Decides when to switch to the ready map
+    policy: Box<dyn SwitchPolicy>,
+    /// Settings the policy was created from
+    policy_settings: SwitchSettings,
+    /// True until the first map was switched to
+    first: bool,
+    /// Map requested by the user for the next switch
+    requested: Option<Request>,
+    /// Switch as soon as the requested map is ready
+    forced: bool,
 }
 
 impl WarpMapHub {
     pub fn new() -> Self {
+        let policy_settings = SwitchSettings::default();
         Self {
             current: None,
+            ready: None,
             next_spec: None,
             worker: WarpMapWorker::new(),
-            next_switch_time: Instant::now(),
+            stale: false,
+            policy: policy_settings.create_policy(),
+            policy_settings,
+            first: true,
+            requested: None,
+            forced: false,
         }
     }
 
-    pub fn step(&mut self, s: &Settings, fx: &ModeBlueprintLibrary, g: &mut Globals) -> Result<()> {
+    /// Switches to the given mode as soon as its map is baked
+    pub fn switch_to(&mut self, mode: ModeId) {
+        self.requested = Some(Request::Mode(mode));
+        self.forced = true;
+    }
+
+    /// Switches to the given spec, e.g. of a bookmark, as soon as its map is baked
+    pub fn show(&mut self, spec: WarpSpec) {
+        self.requested = Some(Request::Spec(spec));
+        self.forced = true;
+    }
+
+    /// Discards all maps, e.g. because they were baked for another resolution. The next map shows
+    /// the given mode, if any, and becomes active as soon as it is baked. A map which is being
+    /// baked isn't waited for but dropped when it's done.
+    pub fn restart(&mut self, mode: Option<ModeId>) {
+        self.stale = !self.worker.is_idle();
+        self.current = None;
+        self.ready = None;
+        self.next_spec = None;
+        self.first = true;
+        self.requested = mode.map(Request::Mode);
+        self.forced = false;
+    }
+
+    /// Bakes the next map as early as possible and switches to it when the policy says so. This
+    /// way a switch lands exactly on the frame chosen by the policy, e.g. on a beat.
+    pub fn step(
+        &mut self,
+        s: &Settings,
+        fx: &ModeBlueprintLibrary,
+        bookmarks: &[Bookmark],
+        g: &mut Globals,
+    ) -> Result<()> {
+        let now = g.clock.now();
+
+        if self.policy_settings != s.switch {
+            self.policy_settings = s.switch.clone();
+            self.policy = s.switch.create_policy();
+            self.policy.on_switch(now, g);
+        }
+
+        if !self.worker.is_idle()
+            && let Some(map) = self.worker.retreive()?
+            && !mem::take(&mut self.stale)
+        {
+            self.ready = Some((self.next_spec.take().unwrap(), map));
+        }
+
         if self.worker.is_idle() {
-            if self.next_switch_time < Instant::now() {
-                let spec = WarpSpec::generate(s, fx, g);
+            // a map baked before the request is outdated
+            if self.requested.is_some() {
+                self.ready = None;
+            }
+
+            if self.ready.is_none() {
+                let spec = match self.requested.take() {
+                    Some(Request::Mode(mode)) => WarpSpec::generate_mode(mode, s, fx, g),
+                    Some(Request::Spec(spec)) => spec,
+                    None => generate(s, fx, bookmarks, g),
+                };
                 self.next_spec = Some(spec.clone());
                 self.worker.start(spec)?;
-                self.next_switch_time = Instant::now() + Duration::from_secs(3);
-                g.fps_at_last_mode_switch = g.fps.reset();
-                g.time_scale = 30. / g.fps_at_last_mode_switch.clamp(10., 120.);
-            }
-        } else {
-            if let Some(map) = self.worker.retreive()? {
-                self.current = Some((self.next_spec.take().unwrap(), map));
+
+                // With a deterministic clock the map must not depend on how fast it is baked
+                if g.clock.is_deterministic() {
+                    let map = self.worker.wait()?;
+                    self.ready = Some((self.next_spec.take().unwrap(), map));
+                }
             }
         }
+
+        let due = self.policy.should_switch(now, g);
+        let pending = self.requested.is_some() || !self.worker.is_idle();
+        let forced = self.forced && !pending;
+        if self.ready.is_some() && (self.first || forced || due) {
+            self.current = self.ready.take();
+            self.first = false;
+            self.forced = false;
+            self.policy.on_switch(now, g);
+            g.fps_at_last_mode_switch = g.fps.reset(now);
+            g.time_scale = 30. / g.fps_at_last_mode_switch.clamp(10., 120.);
+        }
+
         Ok(())
     }
 
-    pub fn fetch(&mut self) -> Option<(WarpSpec, WarpMap)> {
+    pub fn fetch(&mut self) -> Option<(WarpSpec, BakedWarp)> {
         self.current.take()
     }
 }
 
+/// Map requested by the user
+#[allow(clippy::large_enum_variant)]
+enum Request {
+    Mode(ModeId),
+    Spec(WarpSpec),
+}
+
+/// Picks a bookmark with the share of bookmarks in the rotation or generates a new spec. Only
+/// bookmarks of a locked mode are picked.
+fn generate(
+    s: &Settings,
+    fx: &ModeBlueprintLibrary,
+    bookmarks: &[Bookmark],
+    g: &mut Globals,
+) -> WarpSpec {
+    if s.bookmark_share > 0. && !bookmarks.is_empty() {
+        let candidates = bookmarks
+            .iter()
+            .filter(|b| s.mode_prefs.priority().is_none_or(|mode| mode == b.mode))
+            .collect::<Vec<_>>();
+        if !candidates.is_empty() && g.rand.next_01_prom() < s.bookmark_share {
+            let bookmark = candidates[g.rand.next_idx(candidates.len() as u32) as usize];
+            match bookmark.to_spec(s, fx, g) {
+                Ok(spec) => return spec,
+                Err(err) => log::warn!("{err}"),
+            }
+        }
+    }
+    WarpSpec::generate(s, fx, g)
+}
+
 impl Drop for WarpMapHub {
     fn drop(&mut self) {
         self.worker.terminate();
@@ -101,7 +218,7 @@ impl WarpMapWorker {
         }
     }
 
-    pub fn retreive(&mut self) -> Result<Option<WarpMap>> {
+    pub fn retreive(&mut self) -> Result<Option<BakedWarp>> {
         match self.state {
             WarpMapWorkerState::Computing => match self.rx_worker_reply.try_recv() {
                 Ok(WarpMapWorkerReply::Finished(flow_map)) => {
@@ -115,6 +232,20 @@ impl WarpMapWorker {
         }
     }
 
+    /// Blocks until the worker has finished computing the current map
+    pub fn wait(&mut self) -> Result<BakedWarp> {
+        match self.state {
+            WarpMapWorkerState::Computing => match self.rx_worker_reply.recv() {
+                Ok(WarpMapWorkerReply::Finished(flow_map)) => {
+                    self.state = WarpMapWorkerState::Idle;
+                    Ok(flow_map)
+                }
+                Err(mpsc::RecvError) => bail!("worker disconnected"),
+            },
+            WarpMapWorkerState::Idle => bail!("worker idle"),
+        }
+    }
+
     pub fn terminate(&mut self) {
         self.tx_worker_request.send(WarpMapWorkerRequest::Terminate).ok();
         if let Some(h) = self.handle.take() {
@@ -135,7 +266,7 @@ enum WarpMapWorkerRequest {
 }
 
 enum WarpMapWorkerReply {
-    Finished(Image<WarpPixel>),
+    Finished(BakedWarp),
 }
 
 struct WarpMapWorkerThread {
@@ -166,3 +297,37 @@ impl WarpMapWorkerThread {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::painter::{Clock, Painter, YRoi};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn restart_drops_the_map_in_progress() {
+        let painter = Painter::new((240, 320).into(), Clock::realtime(), Some(1));
+        let mut s = painter.settings.clone();
+        let mut g = Globals { clock: Clock::realtime(), ..Default::default() };
+        let mut hub = WarpMapHub::new();
+        hub.step(&s, &painter.library, &[], &mut g).unwrap();
+        assert!(!hub.worker.is_idle());
+
+        // returns at once although the map of the old resolution is still being baked
+        let shape = (480, 640).into();
+        (s.fxh, s.fxw) = (480, 640);
+        s.y_roi = YRoi::letterbox(shape);
+        hub.restart(None);
+
+        let start = Instant::now();
+        let (_, baked) = loop {
+            hub.step(&s, &painter.library, &[], &mut g).unwrap();
+            if let Some(current) = hub.fetch() {
+                break current;
+            }
+            assert!(start.elapsed() < Duration::from_secs(60), "no map was baked");
+            std::thread::sleep(Duration::from_millis(5));
+        };
+        assert_eq!(baked.map.shape(), shape);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    painter::{Globals, ModeId, Settings, WaveformId, color_gen},
+    fx,
+    painter::{Globals, ModeId, REFERENCE_WIDTH, Settings, WaveformId, color_gen},
     utils::{Rgba, RgbaImage, Vec2i},
 };
 
@@ -7,16 +8,31 @@ pub struct Wave {
     beat_mode: bool,
     big_beat_threshold: f32,
     big_beat: bool,
+
+    /// Extra color cycling time accumulated on beats
+    groove: f32,
+
+    /// Brightness factor which falls while the sound is empty
+    fade: f32,
 }
 
 const USE_BEAT_DETECTION: bool = true;
 
+/// Color cycling time added per frame at the full beat pulse
+const GROOVE_COLOR_SPEED: f32 = 3.;
+
+/// Long-term volume up to which quiet audio is shifted
+const SHIFT_REFERENCE_VOL: f32 = 8.;
+
+/// Maximum gain applied by shifting
+const SHIFT_MAX_GAIN: f32 = 4.;
+
 pub const WAVE_5_BLEND_RANGE: usize = 50;
 pub const WAVE_5_SIZE: usize = 314;
 
 impl Wave {
     pub fn new(g: &Globals) -> Self {
-        Self { beat_mode: false, big_beat_threshold: 1.1, big_beat: false }
+        Self { beat_mode: false, big_beat_threshold: 1.1, big_beat: false, groove: 0., fade: 1. }
     }
 
     pub fn render(
@@ -28,7 +44,10 @@ impl Wave {
         s: &Settings,
         g: &Globals,
     ) {
-        let mut base = (g.vol.current() * 6.0 - g.avg_vol * 3.5) * 10. - 40.;
+        // Shifting: quiet recordings are lifted towards the level the brightness formula was tuned
+        // for so that their wave doesn't disappear
+        let gain = (SHIFT_REFERENCE_VOL / g.avg_vol_wide.max(1e-3)).clamp(1., SHIFT_MAX_GAIN);
+        let mut base = (g.vol.current() * 6.0 - g.avg_vol * 3.5) * gain * 10. - 40.;
 
         let avg_vol_uniform = g.vol_narrow.mean();
 
@@ -75,16 +94,15 @@ impl Wave {
             base *= brite_scale;
         }
 
-        // TODO implement "shifting"
-
-        let base = base.clamp(0., 155.);
+        // fade out while the sound is empty
+        self.fade = if g.sound_empty { self.fade * 0.85 } else { (self.fade + 0.1).min(1.) };
 
-        // TODO set SoundEmpty
+        let base = base.clamp(0., 155.) * self.fade;
 
-        // RGB
-        // TODO sync color to sound
+        // RGB, colors cycle faster right after each beat
+        self.groove += g.tempo.pulse() * GROOVE_COLOR_SPEED * g.time_scale;
         let col = {
-            let t = (g.frame as f32 + g.chaser_offset) * g.time_scale;
+            let t = (g.frame as f32 + g.chaser_offset) * g.time_scale + self.groove;
             let f = 7. * (t * 0.006 + 59.).sin() + 5. * (t * 0.0077 + 17.).cos();
             let dat = color_gen(s.gf, f, t, [0.55, 0.50], [10., 37., 32., 16., 87., 25.]);
             Rgba::from_f3([
@@ -94,86 +112,92 @@ impl Wave {
             ])
         };
 
-        // TODO more samples via interpolation for high resolutions
-
         if g.sound_buffer.is_empty() {
             return;
         }
 
+        // Lines show the samples of the reference width along the longer side of the image and
+        // interpolate in between. They are drawn with dots of the size of a reference pixel.
+        let k = s.res_scale();
+        let size = s.dot_size();
+        let ks = s.fxw.max(s.fxh) as f32 / REFERENCE_WIDTH as f32;
+        let lch = |x: u32| g.sound_buffer.lch_at(x as f32 / ks);
+        let rch = |x: u32| g.sound_buffer.rch_at(x as f32 / ks);
+        let (keep, take) = smoothing(0.9, 0.1, ks);
+        let plot = |img: &mut RgbaImage, y: u32, x: u32, col: Rgba| {
+            fx::for_each_in_square(img, (y, x), size, s.y_roi, |c| *c = col);
+        };
+
         match waveform {
             WaveformId(1) => {
                 let (y_center, start, end) = if mode == ModeId(10) {
-                    ((((img.rows() - 90) + img.cols() / 2) / 2) as f32, 10_u32, img.cols() - 10)
+                    let cut = (90. * k) as u32;
+                    let margin = (10. * k) as u32;
+                    (
+                        (((img.rows() - cut) + img.cols() / 2) / 2) as f32,
+                        margin,
+                        img.cols() - margin,
+                    )
                 } else {
                     (center.y as f32, 0, img.cols())
                 };
 
-                let mut zl = g.sound_buffer.lch(start as usize) + y_center;
-                // println!("{zl} {y_center}");
+                let mut zl = lch(start) + y_center;
                 let mut prev_zl;
                 for i in start..end {
                     prev_zl = zl;
-                    zl = g.sound_buffer.lch(i as usize) + y_center;
-                    zl = prev_zl * 0.90 + zl * 0.10;
-                    let yl = zl as u32;
-                    if s.y_roi.contains(yl) {
-                        img[(yl, i)] = col;
-                    }
+                    zl = lch(i) + y_center;
+                    zl = prev_zl * keep + zl * take;
+                    plot(img, zl as u32, i, col);
                 }
             }
             WaveformId(2) => {
                 let div = 0.7;
                 let h1 = center.y as f32 - s.fxh as f32 * 0.12;
                 let h2 = center.y as f32 + s.fxh as f32 * 0.12;
-                let mut zl = g.sound_buffer.lch(0) * div + h1;
-                let mut zr = g.sound_buffer.rch(0) * div + h1;
+                let mut zl = lch(0) * div + h1;
+                let mut zr = rch(0) * div + h1;
                 for j in 0..s.fxw {
                     let prev_zl = zl;
                     let prev_zr = zr;
-                    zl = g.sound_buffer.lch(j as usize) * div + h1;
-                    zr = g.sound_buffer.rch(j as usize) * div + h2;
-                    zl = prev_zl * 0.9 + zl * 0.1;
-                    zr = prev_zr * 0.9 + zr * 0.1;
-                    let yl = zl as u32;
-                    let yr = zr as u32;
-                    if s.y_roi.contains(yl) {
-                        img[(yl, j)] = col;
-                    }
-                    if s.y_roi.contains(yr) {
-                        img[(yr, j)] = col;
-                    }
+                    zl = lch(j) * div + h1;
+                    zr = rch(j) * div + h2;
+                    zl = prev_zl * keep + zl * take;
+                    zr = prev_zr * keep + zr * take;
+                    plot(img, zl as u32, j, col);
+                    plot(img, zr as u32, j, col);
                 }
             }
             WaveformId(3) => {
-                let mut zl = g.sound_buffer.lch(s.y_roi.min as usize) + center.x as f32;
+                let mut zl = lch(s.y_roi.min) + center.x as f32;
                 for i in s.y_roi.min..s.y_roi.max {
                     let prev_zl = zl;
-                    zl = g.sound_buffer.lch(i as usize) + center.x as f32;
-                    zl = prev_zl * 0.9 + zl * 0.1;
+                    zl = lch(i) + center.x as f32;
+                    zl = prev_zl * keep + zl * take;
                     let xl = zl as i32;
                     if 0 <= xl && xl < s.fxw as i32 {
-                        img[(i, xl as u32)] = col;
+                        plot(img, i, xl as u32, col);
                     }
                 }
             }
             WaveformId(4) => {
                 let div = 0.9;
-                let mut zl = g.sound_buffer.lch(s.y_roi.min as usize) * div;
-                let mut zr = g.sound_buffer.rch(s.y_roi.min as usize) * div;
+                let mut zl = lch(s.y_roi.min) * div;
+                let mut zr = rch(s.y_roi.min) * div;
                 for i in s.y_roi.min..s.y_roi.max {
                     let prev_zl = zl;
                     let prev_zr = zr;
-                    zl = g.sound_buffer.lch(i as usize) * div;
-                    zr = g.sound_buffer.rch(i as usize) * div;
-                    zl = prev_zl * 0.9 + zl * 0.1;
-                    zr = prev_zr * 0.9 + zr * 0.1;
+                    zl = lch(i) * div;
+                    zr = rch(i) * div;
+                    zl = prev_zl * keep + zl * take;
+                    zr = prev_zr * keep + zr * take;
                     let xl = zl as i32 + i as i32;
-                    let xr = zr as i32 + i as i32 + (s.fxw - s.fxh) as i32;
+                    let xr = zr as i32 + i as i32 + (s.fxw as i32 - s.fxh as i32);
                     if 0 <= xl && xl < s.fxw as i32 {
-                        img[(i, xl as u32)] = col;
+                        plot(img, i, xl as u32, col);
                     }
                     if 0 <= xr && xr < s.fxw as i32 {
-                        img[(i, xr as u32)] = col;
+                        plot(img, i, xr as u32, col);
                     }
                 }
             }
@@ -193,19 +217,23 @@ impl Wave {
                         }
                     })
                     .collect::<Vec<_>>();
+                let tmp_at = |x: f32| {
+                    let i0 = (x as usize).min(WAVE_5_SIZE - 1);
+                    let i1 = (i0 + 1).min(WAVE_5_SIZE - 1);
+                    tmp[i0] + (tmp[i1] - tmp[i0]) * (x - i0 as f32).clamp(0., 1.)
+                };
 
-                let base_rad = s.fxw as f32 / 640. * 60.0;
+                let base_rad = 60. * k;
+                let (keep, take) = smoothing(0.5, 0.5, k);
 
                 let mut rad = base_rad + tmp[0] * div;
-                for i in 0..WAVE_5_SIZE {
-                    rad = rad * 0.5 + 0.5 * (base_rad + tmp[i] * div);
-                    if rad >= 5. {
-                        let (si, ci) = (i as f32 * 0.02).sin_cos();
+                for i in 0..(WAVE_5_SIZE as f32 * k) as usize {
+                    rad = rad * keep + take * (base_rad + tmp_at(i as f32 / k) * div);
+                    if rad >= 5. * k {
+                        let (si, ci) = (i as f32 * 0.02 / k).sin_cos();
                         let px = (center.x as f32 + rad * ci) as u32;
                         let py = (center.y as f32 + rad * si) as u32;
-                        if px < img.cols() && s.y_roi.contains(py) {
-                            img[(py, px)] = col;
-                        }
+                        plot(img, py, px, col);
                     }
                 }
             }
@@ -213,16 +241,15 @@ impl Wave {
                 let div = 1.2;
                 let ang = (g.frame as f32 * 0.01).sin();
                 let (sinang, cosang) = ang.sin_cos();
+                let (keep, take) = smoothing(0.5, 0.5, k);
                 let mut px2 = g.sound_buffer.lch(0);
                 let mut py2 = g.sound_buffer.rch(0);
-                for i in 0..WAVE_5_SIZE {
-                    px2 = px2 * 0.5 + 0.5 * g.sound_buffer.lch(i) * div;
-                    py2 = py2 * 0.5 + 0.5 * g.sound_buffer.rch(i) * div;
+                for i in 0..(WAVE_5_SIZE as f32 * k) as u32 {
+                    px2 = px2 * keep + take * g.sound_buffer.lch_at(i as f32 / k) * div;
+                    py2 = py2 * keep + take * g.sound_buffer.rch_at(i as f32 / k) * div;
                     let px = (px2 * cosang + py2 * sinang + center.x as f32) as u32;
                     let py = (px2 * (-sinang) + py2 * cosang + center.y as f32) as u32;
-                    if s.y_roi.contains(py) {
-                        img[(py, px)] = col;
-                    }
+                    plot(img, py, px, col);
                 }
             }
             WaveformId(7) | _ => {
@@ -234,18 +261,16 @@ impl Wave {
                         let b = center.y as f32 - m * center.x as f32;
                         for x in 0..s.fxw {
                             let y = (m * x as f32 + b) as u32;
-                            if s.y_roi.contains(y) {
-                                let t = (g.sound_buffer.lch(x as usize) / 64.0).min(1.);
-                                img[(y, x)] = col.scaled(t);
-                            }
+                            let t = (lch(x) / (64.0 * k)).min(1.);
+                            plot(img, y, x, col.scaled(t));
                         }
                     } else {
                         let m = dx / dy;
                         let b = center.x as f32 - m * center.y as f32;
                         for y in s.y_roi.min..s.y_roi.max {
                             let x = (m * y as f32 + b) as u32;
-                            let t = (g.sound_buffer.lch(y as usize) / 64.0).min(1.);
-                            img[(y, x)] = col.scaled(t);
+                            let t = (lch(y) / (64.0 * k)).min(1.);
+                            plot(img, y, x, col.scaled(t));
                         }
                     }
                 }
@@ -253,3 +278,55 @@ impl Wave {
         }
     }
 }
+
+/// Per-pixel factors of the smoothing `z = z * keep + sample * take` which give the same curve
+/// over the reference width at scale `k`
+fn smoothing(keep: f32, take: f32, k: f32) -> (f32, f32) {
+    let keep_k = keep.powf(1. / k);
+    (keep_k, take * (1. - keep_k) / (1. - keep))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::painter::{Clock, ModePrefs, Painter, SoundBuffer};
+    use crate::utils::Shape2;
+
+    /// Offsets from the center of the pixels painted by waveform 5 for a constant sound buffer
+    fn circle(shape: Shape2) -> Vec<(i32, i32)> {
+        let painter = Painter::new(shape, Clock::fixed(30.), Some(1));
+        let s = Settings { mode_prefs: ModePrefs::new(&[ModeId(1)]), ..painter.settings.clone() };
+        let mut g =
+            Globals { sound_buffer: SoundBuffer::from_vec(vec![0.; 1000]), ..Default::default() };
+        g.vol.push(100.);
+
+        let mut img = RgbaImage::black(shape);
+        let center = Vec2i::new(shape.cols() as i32 / 2, shape.rows() as i32 / 2);
+        Wave::new(&g).render(&mut img, center, ModeId(1), WaveformId(5), &s, &g);
+
+        let mut painted = Vec::new();
+        for y in 0..img.rows() {
+            for x in 0..img.cols() {
+                if img[(y, x)].0 != Rgba::BLACK.0 {
+                    painted.push((y as i32 - center.y, x as i32 - center.x));
+                }
+            }
+        }
+        painted
+    }
+
+    #[test]
+    fn wave_5_radius_follows_the_reference_scale() {
+        let reference = circle((480, 640).into());
+        assert!(!reference.is_empty(), "nothing was painted");
+        for &(y, x) in &reference {
+            let r = ((y * y + x * x) as f32).sqrt();
+            assert!((58. ..=62.).contains(&r), "pixel at radius {r}");
+        }
+
+        // wider images don't enlarge the circle, taller ones scale it like the reference
+        assert_eq!(circle((480, 1280).into()), reference);
+        let tall = circle((960, 1280).into());
+        assert!(tall.iter().all(|&(y, x)| ((y * y + x * x) as f32).sqrt() > 115.));
+    }
+}
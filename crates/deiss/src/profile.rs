@@ -0,0 +1,360 @@
+use crate::{
+    painter::{
+        DEFAULT_VOLSCALE, DynamicWarpSettings, ModePrefs, Settings, SpectrumSettings,
+        SwitchSettings, TransitionSettings, WaveformPrefs,
+    },
+    renderer::CrtShaderSettings,
+};
+use eyre::{Result, bail, eyre};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Profile which is used if no other profile is selected
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Changed settings are saved once they didn't change for this long
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// User preferences which are kept between launches.
+///
+/// Missing fields take their default values and unknown fields are ignored, so that profiles of
+/// older and newer versions can be loaded. Unknown top-level entries are written back on save.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub volscale: f32,
+    pub enable_map_dampening: bool,
+
+    /// Color cycling frequencies. Generated from the seed if not set.
+    pub gf: Option<[f32; 6]>,
+
+    pub mode_prefs: ModePrefs,
+    pub waveform_prefs: WaveformPrefs,
+    pub transition: TransitionSettings,
+    pub switch: SwitchSettings,
+    pub dynamic: DynamicWarpSettings,
+    pub spectrum: SpectrumSettings,
+    pub bookmark_share: f32,
+    pub crt: CrtShaderSettings,
+
+    /// Entries this version doesn't know
+    #[serde(flatten)]
+    unknown: toml::Table,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            volscale: DEFAULT_VOLSCALE,
+            enable_map_dampening: false,
+            gf: None,
+            mode_prefs: ModePrefs::default(),
+            waveform_prefs: WaveformPrefs::default(),
+            transition: TransitionSettings::default(),
+            switch: SwitchSettings::default(),
+            dynamic: DynamicWarpSettings::default(),
+            spectrum: SpectrumSettings::default(),
+            bookmark_share: 0.,
+            crt: CrtShaderSettings::default(),
+            unknown: toml::Table::new(),
+        }
+    }
+}
+
+impl Profile {
+    /// Loads a profile. A missing file gives the default profile.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text =
+            fs::read_to_string(path).map_err(|e| eyre!("Failed to read profile {path:?}: {e}"))?;
+        let profile =
+            toml::from_str::<Profile>(&text).map_err(|e| eyre!("Invalid profile {path:?}: {e}"))?;
+        for key in profile.unknown.keys() {
+            log::warn!("Ignoring unknown setting `{key}` in profile {path:?}");
+        }
+        Ok(profile)
+    }
+
+    /// Saves the profile, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|e| eyre!("Failed to create profile directory {dir:?}: {e}"))?;
+        }
+        let text = toml::to_string(self).map_err(|e| eyre!("Failed to serialize profile: {e}"))?;
+        fs::write(path, text).map_err(|e| eyre!("Failed to write profile {path:?}: {e}"))?;
+        Ok(())
+    }
+
+    /// Takes the current settings. Weights of modes which aren't loaded are kept.
+    pub fn update(&mut self, s: &Settings) {
+        self.volscale = s.volscale;
+        self.enable_map_dampening = s.enable_map_dampening;
+        self.gf = Some(s.gf);
+        for &(mode, weight) in s.mode_prefs.weights() {
+            self.mode_prefs.set_weight(mode, weight);
+        }
+        self.mode_prefs.set_priority(s.mode_prefs.priority());
+        self.waveform_prefs = s.waveform_prefs.clone();
+        self.transition = s.transition.clone();
+        self.switch = s.switch.clone();
+        self.dynamic = s.dynamic.clone();
+        self.spectrum = s.spectrum.clone();
+        self.bookmark_share = s.bookmark_share;
+        self.crt = s.crt_shader_settings.clone();
+    }
+
+    /// Applies the profile to painter settings. Preferences for modes and waveforms which don't
+    /// exist are ignored.
+    pub fn apply(&self, s: &mut Settings) {
+        s.volscale = self.volscale;
+        s.enable_map_dampening = self.enable_map_dampening;
+        if let Some(gf) = self.gf {
+            s.gf = gf;
+        }
+        s.mode_prefs.restore(&self.mode_prefs);
+        s.waveform_prefs
+            .set_priority(self.waveform_prefs.priority().filter(|w| (1..=7).contains(&w.0)));
+        s.transition = self.transition.clone();
+        s.switch = self.switch.clone();
+        s.dynamic = self.dynamic.clone();
+        s.spectrum = self.spectrum.clone();
+        s.bookmark_share = self.bookmark_share.clamp(0., 1.);
+        s.crt_shader_settings = self.crt.clone();
+    }
+}
+
+/// Directory of the profiles, e.g. `~/.config/deiss/profiles` on Linux
+pub fn profile_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("deiss").join("profiles"))
+}
+
+/// File of the profile with the given name
+pub fn profile_path(name: &str) -> Result<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+        bail!("Invalid profile name '{name}'. Use letters, digits, '-' and '_'.");
+    }
+    let dir = profile_dir().ok_or_else(|| eyre!("No config directory to store profiles in"))?;
+    Ok(dir.join(format!("{name}.toml")))
+}
+
+/// Names of all saved profiles in alphabetical order
+pub fn list_profiles() -> Vec<String> {
+    let Some(entries) = profile_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut names = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .filter_map(|path| path.file_stem().map(|stem| stem.to_string_lossy().into_owned()))
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+/// Profile which receives all changes of the settings.
+///
+/// Only settings which are changed while the profile is active are saved. Settings which differ
+/// from the profile from the start, e.g. options given on the command line, are not saved unless
+/// they are changed.
+pub struct ActiveProfile {
+    name: String,
+
+    /// Content of the profile file
+    saved: Profile,
+
+    /// Settings when the profile was activated or saved last
+    base: Profile,
+
+    /// Changed settings which aren't saved yet and when they changed last
+    pending: Option<(Profile, Instant)>,
+}
+
+impl ActiveProfile {
+    /// Starts tracking changes of `s`, which are the settings of `profile` with possible overrides
+    pub fn new(name: String, profile: Profile, s: &Settings) -> Self {
+        let base = profile.clone().updated(s);
+        Self { name, saved: profile, base, pending: None }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Saves the settings once they stopped changing
+    pub fn update(&mut self, s: &Settings) {
+        let current = self.saved.clone().updated(s);
+        if current == self.base {
+            self.pending = None;
+            return;
+        }
+
+        match &self.pending {
+            Some((pending, changed)) if *pending == current => {
+                if changed.elapsed() >= SAVE_DELAY {
+                    self.save(current);
+                }
+            }
+            _ => self.pending = Some((current, Instant::now())),
+        }
+    }
+
+    /// Saves changed settings immediately, e.g. before exiting
+    pub fn flush(&mut self, s: &Settings) {
+        let current = self.saved.clone().updated(s);
+        if current != self.base {
+            self.save(current);
+        }
+    }
+
+    /// Saves the settings as a new profile which receives all further changes
+    pub fn save_as(&mut self, name: &str, s: &Settings) -> Result<()> {
+        let profile = self.saved.clone().updated(s);
+        profile.save(&profile_path(name)?)?;
+        log::info!("Saved profile '{name}'");
+        *self =
+            Self { name: name.to_string(), saved: profile.clone(), base: profile, pending: None };
+        Ok(())
+    }
+
+    /// Saves the settings which changed since `base`
+    fn save(&mut self, current: Profile) {
+        self.pending = None;
+        let profile = match with_changes(&self.saved, &self.base, &current) {
+            Ok(profile) => profile,
+            Err(err) => {
+                log::error!("{err}");
+                return;
+            }
+        };
+        match profile_path(&self.name).and_then(|path| profile.save(&path)) {
+            Ok(()) => log::debug!("Saved profile '{}'", self.name),
+            Err(err) => log::error!("{err}"),
+        }
+        // don't retry failed saves every frame
+        self.saved = profile;
+        self.base = current;
+    }
+}
+
+impl Profile {
+    fn updated(mut self, s: &Settings) -> Self {
+        self.update(s);
+        self
+    }
+}
+
+/// `saved` with every entry which differs between `base` and `current` taken from `current`
+fn with_changes(saved: &Profile, base: &Profile, current: &Profile) -> Result<Profile> {
+    let table = |profile: &Profile| {
+        toml::Table::try_from(profile).map_err(|e| eyre!("Failed to serialize profile: {e}"))
+    };
+    let mut merged = table(saved)?;
+    merge_changes(&mut merged, &table(base)?, &table(current)?);
+    merged.try_into().map_err(|e| eyre!("Failed to merge profile changes: {e}"))
+}
+
+fn merge_changes(saved: &mut toml::Table, base: &toml::Table, current: &toml::Table) {
+    for (key, value) in current {
+        match (value, base.get(key), saved.get_mut(key)) {
+            (
+                toml::Value::Table(value),
+                Some(toml::Value::Table(base)),
+                Some(toml::Value::Table(saved)),
+            ) => merge_changes(saved, base, value),
+            (value, base, _) if base != Some(value) => {
+                saved.insert(key.clone(), value.clone());
+            }
+            _ => {}
+        }
+    }
+    // unset options, e.g. a mode which isn't locked anymore
+    for key in base.keys().filter(|key| !current.contains_key(*key)) {
+        saved.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::painter::{ModeId, TransitionCurve};
+
+    #[test]
+    fn overrides_are_not_saved() {
+        let mut saved = Profile::default();
+        saved.mode_prefs.set_weight(ModeId(3), 1);
+
+        // started with `--mode 5 --transition-frames 10`
+        let mut base = saved.clone();
+        base.mode_prefs.set_priority(Some(ModeId(5)));
+        base.transition.frames = 10;
+
+        // changed in the window
+        let mut current = base.clone();
+        current.volscale = 0.5;
+        current.transition.curve = TransitionCurve::Linear;
+        current.mode_prefs.set_weight(ModeId(3), 5);
+
+        let merged = with_changes(&saved, &base, &current).unwrap();
+        assert_eq!(merged.volscale, 0.5);
+        assert_eq!(merged.transition.curve, TransitionCurve::Linear);
+        assert_eq!(merged.transition.frames, saved.transition.frames);
+        assert_eq!(merged.mode_prefs.priority(), None);
+        assert_eq!(merged.mode_prefs.weights(), [(ModeId(3), 5)]);
+    }
+
+    #[test]
+    fn changed_overrides_are_saved() {
+        let saved = Profile::default();
+        let mut base = saved.clone();
+        base.mode_prefs.set_priority(Some(ModeId(5)));
+        base.transition.frames = 10;
+
+        let mut current = base.clone();
+        current.mode_prefs.set_priority(Some(ModeId(7)));
+        current.transition.frames = 20;
+        let merged = with_changes(&saved, &base, &current).unwrap();
+        assert_eq!(merged.mode_prefs.priority(), Some(ModeId(7)));
+        assert_eq!(merged.transition.frames, 20);
+
+        // unlocking the mode removes the lock of the profile
+        let base = current.clone();
+        current.mode_prefs.set_priority(None);
+        let merged = with_changes(&merged, &base, &current).unwrap();
+        assert_eq!(merged.mode_prefs.priority(), None);
+    }
+
+    #[test]
+    fn changed_colors_are_saved() {
+        let saved = Profile { gf: Some([0.02; 6]), ..Default::default() };
+        let mut current = saved.clone();
+        current.gf = Some([0.025; 6]);
+        let merged = with_changes(&saved, &saved, &current).unwrap();
+        assert_eq!(merged.gf, Some([0.025; 6]));
+
+        // colors given by the seed aren't saved unless they are changed
+        let mut base = saved.clone();
+        base.gf = Some([0.03; 6]);
+        let merged = with_changes(&saved, &base, &base).unwrap();
+        assert_eq!(merged.gf, Some([0.02; 6]));
+    }
+
+    #[test]
+    fn reverted_changes_are_saved() {
+        let saved = Profile::default();
+        let mut changed = saved.clone();
+        changed.volscale = 0.5;
+        let merged = with_changes(&saved, &saved, &changed).unwrap();
+        assert_eq!(merged.volscale, 0.5);
+
+        // after a save the saved settings are the base
+        let merged = with_changes(&merged, &changed, &saved).unwrap();
+        assert_eq!(merged, saved);
+    }
+}
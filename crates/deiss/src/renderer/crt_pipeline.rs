@@ -2,9 +2,11 @@ use crate::{
     renderer::{Gpu, Shader, Texture, create_pipeline},
     utils::{RgbaImage, Shape2, Vec2f},
 };
+use serde::{Deserialize, Serialize};
 use wgpu::util::DeviceExt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CrtShaderSettings {
     pub warp_enabled: bool,
     pub warp_strength: f32,
@@ -187,6 +189,7 @@ impl CrtPipeline {
 
         settings: &CrtShaderSettings,
     ) {
+        self.paint_tex.reshape(gpu, image.shape());
         self.paint_tex.write(gpu, image);
 
         self.prev_tex.reshape(gpu, display_shape);
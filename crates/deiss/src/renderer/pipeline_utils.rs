@@ -1,10 +1,11 @@
-use std::ops;
+use std::{ops, sync::mpsc};
 
+use eyre::{Result, eyre};
 use wgpu::Origin3d;
 
 use crate::{
     renderer::Gpu,
-    utils::{RgbaImage, Shape2},
+    utils::{Rgba, RgbaImage, Shape2},
 };
 
 pub struct Shader<'a> {
@@ -195,6 +196,92 @@ impl Texture {
     }
 }
 
+impl Texture {
+    /// Copies an Rgba16Float texture back from the GPU. Colors are encoded as sRGB like on an sRGB
+    /// surface, so the image looks the same as the presented texture. Blocks until the GPU is done.
+    pub fn read_srgb(&self, gpu: &Gpu) -> Result<RgbaImage> {
+        assert_eq!(self.format, wgpu::TextureFormat::Rgba16Float);
+
+        // rows of the buffer must be aligned
+        let row_bytes =
+            (8 * self.shape.cols()).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = gpu.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback buffer"),
+            size: row_bytes as u64 * self.shape.rows() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = gpu.device().create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            wgpu::TexelCopyTextureInfo {
+                texture: &self.tex,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row_bytes),
+                    rows_per_image: Some(self.shape.rows()),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.shape.cols(),
+                height: self.shape.rows(),
+                depth_or_array_layers: 1,
+            },
+        );
+        gpu.queue().submit([encoder.finish()]);
+
+        let (tx, rx) = mpsc::channel();
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            tx.send(result).ok();
+        });
+        gpu.device()
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|e| eyre!("Failed to wait for the GPU: {e}"))?;
+        rx.recv()?.map_err(|e| eyre!("Failed to map readback buffer: {e}"))?;
+
+        let data = slice.get_mapped_range();
+        let img = RgbaImage::from_fn(self.shape, |(i, j)| {
+            let px = (i * row_bytes + j * 8) as usize;
+            let channel = |c: usize| {
+                let half = u16::from_le_bytes([data[px + 2 * c], data[px + 2 * c + 1]]);
+                linear_to_srgb(f16_to_f32(half))
+            };
+            Rgba([channel(0), channel(1), channel(2), 255])
+        });
+        drop(data);
+        buffer.unmap();
+
+        Ok(img)
+    }
+}
+
+/// Converts an IEEE 754 half precision float to f32
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1. } else { 1. };
+    let exp = ((half >> 10) & 0x1f) as i32;
+    let frac = (half & 0x3ff) as f32 / 1024.;
+    sign * match exp {
+        0 => frac * 2f32.powi(-14),
+        31 if frac == 0. => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1. + frac) * 2f32.powi(exp - 15),
+    }
+}
+
+/// Encodes a linear color value as 8 bit sRGB
+fn linear_to_srgb(v: f32) -> u8 {
+    let v = v.clamp(0., 1.);
+    let s = if v <= 0.003_130_8 { 12.92 * v } else { 1.055 * v.powf(1. / 2.4) - 0.055 };
+    (s * 255. + 0.5) as u8
+}
+
 impl ops::Deref for Texture {
     type Target = wgpu::TextureView;
 
@@ -202,3 +289,39 @@ impl ops::Deref for Texture {
         &self.view
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f16_values() {
+        assert_eq!(f16_to_f32(0x0000), 0.);
+        assert!(f16_to_f32(0x8000).is_sign_negative());
+        assert_eq!(f16_to_f32(0x3c00), 1.);
+        assert_eq!(f16_to_f32(0xc000), -2.);
+        assert_eq!(f16_to_f32(0x3800), 0.5);
+        assert_eq!(f16_to_f32(0x3555), 0.333_251_95);
+        assert_eq!(f16_to_f32(0x7bff), 65_504.);
+    }
+
+    #[test]
+    fn f16_subnormals_and_specials() {
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023. * 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x0400), 2f32.powi(-14));
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn srgb_encoding() {
+        assert_eq!(linear_to_srgb(0.), 0);
+        assert_eq!(linear_to_srgb(1.), 255);
+        assert_eq!(linear_to_srgb(0.5), 188);
+        assert_eq!(linear_to_srgb(0.002), 7);
+        assert_eq!(linear_to_srgb(-1.), 0);
+        assert_eq!(linear_to_srgb(2.), 255);
+    }
+}
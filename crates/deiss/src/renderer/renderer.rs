@@ -3,6 +3,7 @@ use crate::{
     renderer::{CrtPipeline, EguiPipeline, Gpu, PresentPipeline},
     utils::{RgbaImage, Shape2},
 };
+use eyre::Result;
 use winit::{event::WindowEvent, window::Window};
 
 pub struct Renderer {
@@ -53,6 +54,11 @@ impl Renderer {
         gpu.queue().submit([encoder.finish()]);
     }
 
+    /// Reads back the last frame after the CRT effect, without the GUI
+    pub fn read_crt_output(&self, gpu: &Gpu) -> Result<RgbaImage> {
+        self.crt_pipeline.output().read_srgb(gpu)
+    }
+
     pub fn render_gui(
         &mut self,
         gpu: &Gpu,
@@ -0,0 +1,489 @@
+use core::fmt;
+
+/// Arithmetic expression compiled to bytecode for a stack machine.
+///
+/// Expressions use numbers, variables, `+ - * / % ^`, comparisons `< <= > >= == !=` (1.0 if true,
+/// 0.0 otherwise), parentheses and the functions listed in [FUNCTIONS]. `if(c, a, b)` is `a` if
+/// `c` is positive and `b` otherwise. The constants `pi` and `e` are always defined.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    ops: Vec<Op>,
+}
+
+/// Maximum stack depth of an expression, deeper expressions fail to compile
+const MAX_DEPTH: usize = 32;
+
+/// Maximum nesting of subexpressions, e.g. parentheses, function arguments and the right sides of
+/// `^`. Limits the recursion of the parser.
+const MAX_NESTING: usize = 64;
+
+type BinaryFn = fn(f32, f32) -> f32;
+
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Const(f32),
+    Var(usize),
+    Neg,
+    Bin(BinaryFn),
+    Call1(fn(f32) -> f32),
+    Call2(BinaryFn),
+    Call3(fn(f32, f32, f32) -> f32),
+}
+
+/// Functions by name with their number of arguments
+pub const FUNCTIONS: [(&str, usize); 20] = [
+    ("sin", 1),
+    ("cos", 1),
+    ("tan", 1),
+    ("asin", 1),
+    ("acos", 1),
+    ("atan", 1),
+    ("sqrt", 1),
+    ("abs", 1),
+    ("exp", 1),
+    ("ln", 1),
+    ("floor", 1),
+    ("ceil", 1),
+    ("fract", 1),
+    ("sign", 1),
+    ("atan2", 2),
+    ("pow", 2),
+    ("min", 2),
+    ("max", 2),
+    ("clamp", 3),
+    ("if", 3),
+];
+
+fn function_op(name: &str) -> Option<Op> {
+    Some(match name {
+        "sin" => Op::Call1(f32::sin),
+        "cos" => Op::Call1(f32::cos),
+        "tan" => Op::Call1(f32::tan),
+        "asin" => Op::Call1(f32::asin),
+        "acos" => Op::Call1(f32::acos),
+        "atan" => Op::Call1(f32::atan),
+        "sqrt" => Op::Call1(f32::sqrt),
+        "abs" => Op::Call1(f32::abs),
+        "exp" => Op::Call1(f32::exp),
+        "ln" => Op::Call1(f32::ln),
+        "floor" => Op::Call1(f32::floor),
+        "ceil" => Op::Call1(f32::ceil),
+        "fract" => Op::Call1(f32::fract),
+        "sign" => Op::Call1(|v| if v == 0. { 0. } else { v.signum() }),
+        "atan2" => Op::Call2(f32::atan2),
+        "pow" => Op::Call2(f32::powf),
+        "min" => Op::Call2(f32::min),
+        "max" => Op::Call2(f32::max),
+        "clamp" => Op::Call3(|v, lo, hi| v.max(lo).min(hi)),
+        "if" => Op::Call3(|c, a, b| if c > 0. { a } else { b }),
+        _ => return None,
+    })
+}
+
+/// Compile error with the character position in the source
+#[derive(Debug, Clone)]
+pub struct ExprError {
+    pub pos: usize,
+    pub msg: String,
+    /// The expression which failed to compile
+    pub src: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {} in '{}'", self.msg, self.pos + 1, self.src)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+/// Position and message of an error during compilation
+type CompileResult<T> = Result<T, (usize, String)>;
+
+impl Expr {
+    /// Compiles `src`. Variables are looked up by name in `vars` and their values are passed to
+    /// [Self::eval] in the same order.
+    pub fn compile(src: &str, vars: &[&str]) -> Result<Self, ExprError> {
+        Self::compile_ops(src, vars).map_err(|(pos, msg)| ExprError { pos, msg, src: src.into() })
+    }
+
+    fn compile_ops(src: &str, vars: &[&str]) -> CompileResult<Self> {
+        let tokens = tokenize(src)?;
+        let mut parser =
+            Parser { tokens, pos: 0, vars, ops: Vec::new(), end: src.len(), nesting: 0 };
+        parser.expr(0)?;
+        if let Some(&(pos, ref token)) = parser.tokens.get(parser.pos) {
+            return Err((pos, format!("unexpected {token}")));
+        }
+
+        let expr = Self { ops: parser.ops };
+        if expr.depth() > MAX_DEPTH {
+            return Err((0, "expression is too deeply nested".into()));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluates the expression with the values of the variables given to [Self::compile]
+    pub fn eval(&self, vars: &[f32]) -> f32 {
+        let mut stack = [0.; MAX_DEPTH];
+        let mut n = 0;
+        for op in &self.ops {
+            match *op {
+                Op::Const(v) => {
+                    stack[n] = v;
+                    n += 1;
+                }
+                Op::Var(i) => {
+                    stack[n] = vars[i];
+                    n += 1;
+                }
+                Op::Neg => stack[n - 1] = -stack[n - 1],
+                Op::Call1(f) => stack[n - 1] = f(stack[n - 1]),
+                Op::Bin(f) | Op::Call2(f) => {
+                    n -= 1;
+                    stack[n - 1] = f(stack[n - 1], stack[n]);
+                }
+                Op::Call3(f) => {
+                    n -= 2;
+                    stack[n - 1] = f(stack[n - 1], stack[n], stack[n + 1]);
+                }
+            }
+        }
+        stack[0]
+    }
+
+    /// True if the variable with index `var` is used
+    pub fn uses_var(&self, var: usize) -> bool {
+        self.ops.iter().any(|op| matches!(op, Op::Var(i) if *i == var))
+    }
+
+    /// Maximum number of values on the stack during evaluation
+    fn depth(&self) -> usize {
+        let (mut n, mut max) = (0_usize, 0);
+        for op in &self.ops {
+            match op {
+                Op::Const(_) | Op::Var(_) => n += 1,
+                Op::Neg | Op::Call1(_) => {}
+                Op::Bin(_) | Op::Call2(_) => n -= 1,
+                Op::Call3(_) => n -= 2,
+            }
+            max = max.max(n);
+        }
+        max
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f32),
+    Ident(String),
+    Op(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(v) => write!(f, "number {v}"),
+            Token::Ident(name) => write!(f, "'{name}'"),
+            Token::Op(op) => write!(f, "'{op}'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+/// Operators ordered so that longer operators match first
+const OPERATORS: [&str; 12] = ["<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "%", "^"];
+
+fn tokenize(src: &str) -> CompileResult<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut rest = src;
+    while let Some(c) = rest.chars().next() {
+        let pos = src.len() - rest.len();
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c.is_ascii_digit() || c == '.' {
+            let len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+            // exponent such as 1e-3
+            let len = match rest[len..].strip_prefix(['e', 'E']) {
+                Some(exp) => {
+                    let sign = exp.starts_with(['+', '-']) as usize;
+                    let digits =
+                        exp[sign..].find(|c: char| !c.is_ascii_digit()).unwrap_or(exp.len() - sign);
+                    if digits > 0 { len + 1 + sign + digits } else { len }
+                }
+                None => len,
+            };
+            let v = rest[..len]
+                .parse::<f32>()
+                .map_err(|_| (pos, format!("invalid number '{}'", &rest[..len])))?;
+            tokens.push((pos, Token::Num(v)));
+            rest = &rest[len..];
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len =
+                rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            tokens.push((pos, Token::Ident(rest[..len].to_string())));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push((pos, Token::Op(op)));
+            rest = &rest[op.len()..];
+        } else {
+            let token = match c {
+                '(' => Token::Open,
+                ')' => Token::Close,
+                ',' => Token::Comma,
+                _ => return Err((pos, format!("unexpected character '{c}'"))),
+            };
+            tokens.push((pos, token));
+            rest = &rest[1..];
+        }
+    }
+    Ok(tokens)
+}
+
+/// Binding power and operation of binary operators. `^` is right associative.
+fn binary_op(op: &str) -> Option<(u8, BinaryFn)> {
+    Some(match op {
+        "==" => (1, |a, b| (a == b) as u32 as f32),
+        "!=" => (1, |a, b| (a != b) as u32 as f32),
+        "<" => (2, |a, b| (a < b) as u32 as f32),
+        "<=" => (2, |a, b| (a <= b) as u32 as f32),
+        ">" => (2, |a, b| (a > b) as u32 as f32),
+        ">=" => (2, |a, b| (a >= b) as u32 as f32),
+        "+" => (3, |a, b| a + b),
+        "-" => (3, |a, b| a - b),
+        "*" => (4, |a, b| a * b),
+        "/" => (4, |a, b| a / b),
+        "%" => (4, |a, b| a.rem_euclid(b)),
+        "^" => (6, f32::powf),
+        _ => return None,
+    })
+}
+
+/// Binding power of unary minus, binds weaker than `^` so that `-x^2` is `-(x^2)`
+const UNARY_POWER: u8 = 5;
+
+/// Pratt parser which emits ops in postfix order
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    vars: &'a [&'a str],
+    ops: Vec<Op>,
+    /// Position reported for errors at the end of the source
+    end: usize,
+    /// Number of subexpressions which are currently parsed
+    nesting: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> CompileResult<(usize, Token)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or((self.end, "unexpected end of expression".into()))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn expect(&mut self, expected: Token) -> CompileResult<()> {
+        let (pos, token) = self.next()?;
+        if token != expected {
+            return Err((pos, format!("expected {expected}, found {token}")));
+        }
+        Ok(())
+    }
+
+    fn expr(&mut self, min_power: u8) -> CompileResult<()> {
+        if self.nesting >= MAX_NESTING {
+            let pos = self.tokens.get(self.pos).map_or(self.end, |&(pos, _)| pos);
+            return Err((pos, "expression is too deeply nested".into()));
+        }
+        self.nesting += 1;
+        let result = self.binary(min_power);
+        self.nesting -= 1;
+        result
+    }
+
+    /// Operand followed by binary operators which bind at least with `min_power`
+    fn binary(&mut self, min_power: u8) -> CompileResult<()> {
+        self.operand()?;
+
+        while let Some(Token::Op(op)) = self.peek() {
+            let Some((power, f)) = binary_op(op) else {
+                break;
+            };
+            if power < min_power {
+                break;
+            }
+            let right_power = if *op == "^" { power } else { power + 1 };
+            self.pos += 1;
+            self.expr(right_power)?;
+            self.ops.push(Op::Bin(f));
+        }
+        Ok(())
+    }
+
+    fn operand(&mut self) -> CompileResult<()> {
+        let (pos, token) = self.next()?;
+        match token {
+            Token::Num(v) => self.ops.push(Op::Const(v)),
+            Token::Op("-") => {
+                self.expr(UNARY_POWER)?;
+                self.ops.push(Op::Neg);
+            }
+            Token::Open => {
+                self.expr(0)?;
+                self.expect(Token::Close)?;
+            }
+            Token::Ident(name) if self.peek() == Some(&Token::Open) => self.call(pos, &name)?,
+            Token::Ident(name) => {
+                if let Some(i) = self.vars.iter().position(|v| *v == name) {
+                    self.ops.push(Op::Var(i));
+                } else if name == "pi" {
+                    self.ops.push(Op::Const(core::f32::consts::PI));
+                } else if name == "e" {
+                    self.ops.push(Op::Const(core::f32::consts::E));
+                } else {
+                    let msg = format!("unknown variable '{name}', expected one of {:?}", self.vars);
+                    return Err((pos, msg));
+                }
+            }
+            token => return Err((pos, format!("unexpected {token}"))),
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, pos: usize, name: &str) -> CompileResult<()> {
+        let Some(&(_, arity)) = FUNCTIONS.iter().find(|(f, _)| *f == name) else {
+            return Err((pos, format!("unknown function '{name}'")));
+        };
+
+        self.expect(Token::Open)?;
+        for i in 0..arity {
+            if i > 0 {
+                self.expect(Token::Comma)?;
+            }
+            self.expr(0)?;
+        }
+        let (close, token) = self.next()?;
+        if token != Token::Close {
+            let msg = format!("'{name}' takes {arity} argument(s), found {token}");
+            return Err((close, msg));
+        }
+
+        self.ops.extend(function_op(name));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(src: &str) -> f32 {
+        Expr::compile(src, &["x", "y"]).unwrap().eval(&[3., -2.])
+    }
+
+    fn error(src: &str) -> ExprError {
+        Expr::compile(src, &["x", "y"]).unwrap_err()
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.);
+        assert_eq!(eval("(1 + 2) * 3"), 9.);
+        assert_eq!(eval("10 - 4 - 3"), 3.);
+        assert_eq!(eval("12 / 3 / 2"), 2.);
+        assert_eq!(eval("2 * 3 ^ 2"), 18.);
+        assert_eq!(eval("1 + 1 < 3"), 1.);
+        assert_eq!(eval("1 < 2 == 2 < 1"), 0.);
+        assert_eq!(eval("7 % 4 + 1"), 4.);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(eval("2 ^ 3 ^ 2"), 512.);
+        assert_eq!(eval("(2 ^ 3) ^ 2"), 64.);
+        assert_eq!(eval("2 ^ -1"), 0.5);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(eval("-x ^ 2"), -9.);
+        assert_eq!(eval("(-x) ^ 2"), 9.);
+        assert_eq!(eval("--x"), 3.);
+        assert_eq!(eval("-y * 2"), 4.);
+        // the remainder is always positive
+        assert_eq!(eval("-1 % 3"), 2.);
+    }
+
+    #[test]
+    fn numbers_variables_and_functions() {
+        assert_eq!(eval("1.5e2 + 2E-1 + .5"), 150.7);
+        assert_eq!(eval("x * y"), -6.);
+        assert_eq!(eval("pi"), core::f32::consts::PI);
+        assert_eq!(eval("e"), core::f32::consts::E);
+        assert_eq!(eval("min(x, y) + max(x, y)"), 1.);
+        assert_eq!(eval("clamp(x, 0, 1)"), 1.);
+        assert_eq!(eval("if(y, 1, 2)"), 2.);
+        assert_eq!(eval("sign(0) + sign(y)"), -1.);
+        assert_eq!(eval("atan2(0, -1)"), core::f32::consts::PI);
+
+        let expr = Expr::compile("sin(y)", &["x", "y"]).unwrap();
+        assert!(!expr.uses_var(0) && expr.uses_var(1));
+    }
+
+    #[test]
+    fn arity_errors() {
+        assert!(error("sin(1, 2)").msg.contains("'sin' takes 1 argument(s)"));
+        let err = error("min(1)");
+        assert_eq!(err.pos, 5);
+        assert!(err.msg.contains("expected ','"), "{err}");
+        assert!(error("clamp()").msg.contains("unexpected ')'"));
+    }
+
+    #[test]
+    fn unknown_identifiers() {
+        let err = error("x + z");
+        assert_eq!(err.pos, 4);
+        assert!(err.msg.contains("unknown variable 'z'"), "{err}");
+        assert!(error("foo(1)").msg.contains("unknown function 'foo'"));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert!(error("").msg.contains("unexpected end"));
+        assert!(error("1 +").msg.contains("unexpected end"));
+        assert!(error("(1").msg.contains("unexpected end"));
+        assert!(error("1 2").msg.contains("unexpected number 2"));
+        assert!(error("1 $ 2").msg.contains("unexpected character '$'"));
+        assert_eq!(error("x +").to_string(), "unexpected end of expression at position 4 in 'x +'");
+    }
+
+    #[test]
+    fn depth_limit() {
+        // the nesting limit holds deep sources which would overflow the parser's stack
+        for src in [
+            format!("{}x{}", "(".repeat(100_000), ")".repeat(100_000)),
+            format!("{}x", "-".repeat(100_000)),
+            format!("{}x", "x^".repeat(100_000)),
+            format!("{}x{}", "sin(".repeat(100_000), ")".repeat(100_000)),
+        ] {
+            assert!(error(&src).msg.contains("too deeply nested"));
+        }
+
+        // nesting within the limits
+        let parens = format!("{}x{}", "(".repeat(50), ")".repeat(50));
+        assert_eq!(eval(&parens), 3.);
+
+        // too many values on the evaluation stack
+        let sum = |n| format!("{}1{}", "1 + (".repeat(n), ")".repeat(n));
+        assert_eq!(eval(&sum(MAX_DEPTH - 1)), MAX_DEPTH as f32);
+        assert!(error(&sum(MAX_DEPTH)).msg.contains("too deeply nested"));
+    }
+}
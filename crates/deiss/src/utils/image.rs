@@ -1,5 +1,6 @@
 use crate::utils::Shape2;
 use core::ops;
+use rayon::prelude::*;
 
 #[derive(Clone)]
 pub struct Image<T> {
@@ -23,6 +24,25 @@ impl<T> Image<T> {
         Self { shape, buffer }
     }
 
+    /// Like [Self::from_fn] but evaluates rows in parallel. Runs [Self::from_fn] if rayon has a
+    /// single thread, which avoids the overhead of splitting the work.
+    pub fn par_from_fn(shape: Shape2, f: impl Fn((u32, u32)) -> T + Sync) -> Self
+    where
+        T: Send,
+    {
+        if rayon::current_num_threads() == 1 {
+            return Self::from_fn(shape, f);
+        }
+
+        let cols = shape.cols() as usize;
+        let buffer = (0..shape.len())
+            .into_par_iter()
+            .with_min_len(cols)
+            .map(|k| f(((k / cols) as u32, (k % cols) as u32)))
+            .collect();
+        Self { shape, buffer }
+    }
+
     pub fn from_value(shape: Shape2, value: T) -> Self
     where
         T: Copy,
@@ -54,6 +74,16 @@ impl<T> Image<T> {
         &mut self.buffer
     }
 
+    /// Nearest neighbor resampling to another shape
+    pub fn resized(&self, shape: Shape2) -> Self
+    where
+        T: Copy,
+    {
+        Self::from_fn(shape, |(i, j)| {
+            self[(i * self.rows() / shape.rows(), j * self.cols() / shape.cols())]
+        })
+    }
+
     pub fn apply(&mut self, mut f: impl FnMut(T) -> T)
     where
         T: Copy,
@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Minstd {
     u: u64,
 }
@@ -18,6 +18,16 @@ impl Minstd {
         out
     }
 
+    /// Continues the sequence of a generator whose [Self::state] was saved
+    pub fn from_state(state: u64) -> Self {
+        Self { u: state.clamp(1, 2_147_483_646) }
+    }
+
+    /// Internal state from which the sequence continues
+    pub fn state(&self) -> u64 {
+        self.u
+    }
+
     pub fn next(&mut self) -> u32 {
         self.u = (self.u * 48_271) % 2_147_483_647;
         self.u as u32
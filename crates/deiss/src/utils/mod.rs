@@ -1,13 +1,19 @@
+mod expr;
 mod image;
 mod minstd;
 mod rgba;
 mod rot2;
 mod shape;
+#[cfg(test)]
+mod temp_dir;
 mod vec2;
 
+pub use expr::*;
 pub use image::*;
 pub use minstd::*;
 pub use rgba::*;
 pub use rot2::*;
 pub use shape::*;
+#[cfg(test)]
+pub use temp_dir::*;
 pub use vec2::*;
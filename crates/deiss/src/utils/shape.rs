@@ -1,3 +1,5 @@
+use crate::utils::Vec2f;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Shape2(u32, u32);
 
@@ -20,6 +22,19 @@ impl Shape2 {
     pub fn offset(&self, (i, j): (u32, u32)) -> usize {
         (i * self.cols() + j) as usize
     }
+
+    /// Shape with about the same number of pixels and the aspect ratio of `other`. The aspect
+    /// ratio is limited to 1:4 to 4:1.
+    pub fn with_aspect_of(&self, other: Shape2) -> Shape2 {
+        let aspect = (other.cols() as f32 / other.rows().max(1) as f32).clamp(0.25, 4.);
+        let rows = (self.len() as f32 / aspect).sqrt();
+        Shape2(rows.round().max(1.) as u32, (rows * aspect).round().max(1.) as u32)
+    }
+
+    /// Width and height as a vector
+    pub fn size(&self) -> Vec2f {
+        Vec2f::new(self.cols() as f32, self.rows() as f32)
+    }
 }
 
 impl From<(u32, u32)> for Shape2 {
@@ -33,3 +48,28 @@ impl From<Shape2> for (u32, u32) {
         (shape.rows(), shape.cols())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_aspect_of_keeps_the_pixel_count() {
+        let shape = Shape2(480, 640);
+        assert_eq!(shape.with_aspect_of(Shape2(600, 800)), shape);
+        assert_eq!(shape.with_aspect_of(Shape2(1080, 1920)), Shape2(416, 739));
+        assert_eq!(shape.with_aspect_of(Shape2(1920, 1080)), Shape2(739, 416));
+    }
+
+    #[test]
+    fn with_aspect_of_limits_the_aspect_ratio() {
+        let shape = Shape2(480, 640);
+        for other in [Shape2(100, 1000), Shape2(0, 100)] {
+            let wide = shape.with_aspect_of(other);
+            assert!((wide.cols() as f32 / wide.rows() as f32 - 4.).abs() < 0.01, "{wide:?}");
+        }
+        let tall = shape.with_aspect_of(Shape2(1000, 10));
+        assert!((tall.rows() as f32 / tall.cols() as f32 - 4.).abs() < 0.01, "{tall:?}");
+        assert!(tall.len().abs_diff(shape.len()) < 1000);
+    }
+}
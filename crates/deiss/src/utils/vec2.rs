@@ -1,10 +1,11 @@
 use core::ops;
+use serde::{Deserialize, Serialize};
 
 pub type Vec2f = Vec2<f32>;
 
 pub type Vec2i = Vec2<i32>;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Vec2<K> {
     pub x: K,
     pub y: K,